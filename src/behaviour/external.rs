@@ -0,0 +1,166 @@
+//! Bots running as separate processes.
+//!
+//! The bot is started once per game and talks to the game over its
+//! standard input and output, one line at a time. Whenever it is the bot's
+//! turn it receives the full game state:
+//!
+//! ```text
+//! turn <turn>
+//! you <player index>
+//! rules alternating|simultaneous
+//! time <milliseconds allowed for this move>
+//! board <width> <height>
+//! topology bounded|wrap-horizontal|wrap-vertical|torus
+//! player <index> <row> <column> <N|E|S|W> <1 if alive, 0 otherwise>
+//! ...
+//! <height rows of width characters>
+//! end
+//! ```
+//!
+//! Board rows use `.` for empty cells, `X` for crashes, `#` for the map's
+//! obstacles, `a`, `b`, ... for the walls left by players 0, 1, ... and
+//! `A`, `B`, ... for their heads. Rows and columns are numbered from 0 at
+//! the top left.
+//!
+//! The bot answers with a single line `<turn> <F|L|R>` to move forward or
+//! turn left or right. Answers tagged with a stale turn are ignored. Under
+//! a Fischer clock the time limit shrinks and grows with the bot's bank;
+//! what happens to an answer arriving after it depends on the match's
+//! late-move policy. When the game is over the bot receives `gameover` and
+//! its input is closed. If the bot cannot be started, exits or closes its
+//! output during a game, its player crashes.
+//!
+//! The bot's standard error goes straight to the program's, so that bots
+//! can log freely; redirect it (`2>bot.log`) when watching a match in the
+//! terminal.
+//!
+//! Bots that make random choices should seed them from the `RON_SEED`
+//! environment variable so that matches can be reproduced.
+
+use game::{Action, Behaviour, GameState, PlayerIndex, StateMessage, ActionMessage};
+use game::{Alternating, Simultaneous, Empty, PlayerWall, PlayerHead, Crash, Obstacle};
+use std::io::{BufferedReader, IoResult};
+use std::io::process::{Command, InheritFd};
+
+fn tile_symbol(base: char, player: PlayerIndex) -> char {
+    ((base as u8) + (player as u8)) as char
+}
+
+fn write_state(writer: &mut Writer, me: PlayerIndex, game: &GameState, time_ms: i64) -> IoResult<()> {
+    try!(writer.write_str(format!("turn {}\n", game.turn).as_slice()));
+    try!(writer.write_str(format!("you {}\n", me).as_slice()));
+    try!(writer.write_str(match game.rules {
+        Alternating => "rules alternating\n",
+        Simultaneous => "rules simultaneous\n"
+    }));
+    try!(writer.write_str(format!("time {}\n", time_ms).as_slice()));
+    try!(writer.write_str(format!("board {} {}\n", game.board_width, game.board_height).as_slice()));
+    try!(writer.write_str(format!("topology {}\n", game.topology.name()).as_slice()));
+    for (i, player) in game.players.iter().enumerate() {
+        let (row, column) = player.position;
+        try!(writer.write_str(format!("player {} {} {} {} {}\n", i, row, column, player.direction.symbol(),
+                                      if player.is_alive { 1u } else { 0u }).as_slice()));
+    }
+    for row in game.board.iter() {
+        let line: String = row.iter().map(|tile| match *tile {
+            Empty => '.',
+            PlayerWall(p) => tile_symbol('a', p),
+            PlayerHead(p) => tile_symbol('A', p),
+            Crash => 'X',
+            Obstacle => '#'
+        }).collect();
+        try!(writer.write_line(line.as_slice()));
+    }
+    try!(writer.write_str("end\n"));
+    writer.flush()
+}
+
+fn parse_answer(line: &str) -> Option<(uint, Action)> {
+    let words: Vec<&str> = line.words().collect();
+    if words.len() != 2 || words[1].len() != 1 {
+        return None;
+    }
+    match (from_str(words[0]), Action::from_symbol(words[1].char_at(0))) {
+        (Some(turn), Some(action)) => Some((turn, action)),
+        _ => None
+    }
+}
+
+/// Plays one game with a new process running `program` and the first
+/// state of the game, `first`. Returns why the bot failed, if it did.
+fn play_game(program: &String, args: &[String], seed: u64, first: StateMessage,
+             state_receiver: &Receiver<StateMessage>, action_sender: &Sender<ActionMessage>) -> Option<String> {
+    let mut process = match Command::new(program.as_slice()).args(args)
+                            .env("RON_SEED", seed.to_string()).stderr(InheritFd(2)).spawn() {
+        Ok(process) => process,
+        Err(e) => return Some(format!("could not start bot {}: {}", program, e))
+    };
+    let mut input = process.stdin.take().unwrap();
+    let mut output = BufferedReader::new(process.stdout.take().unwrap());
+    let mut failure = None;
+
+    let mut message = first;
+    loop {
+        let (id, me, game, time_ms) = message;
+        if game.is_over() {
+            debug!("Game is over, stopping the bot.");
+            let _ = input.write_str("gameover\n");
+            break;
+        };
+        match write_state(&mut input, me, &game, time_ms) {
+            Ok(()) => {}
+            Err(e) => {
+                failure = Some(format!("could not send state to bot {}: {}", program, e));
+                break;
+            }
+        }
+        match output.read_line() {
+            Ok(line) => match parse_answer(line.as_slice()) {
+                Some((turn, action)) => {
+                    debug!("Sending action {}", action);
+                    action_sender.send((id, turn, action));
+                }
+                None => warn!("Bot {} sent an invalid answer: {}", program, line.as_slice().trim())
+            },
+            Err(e) => {
+                failure = Some(format!("could not read answer from bot {}: {}", program, e));
+                break;
+            }
+        }
+        message = match state_receiver.recv_opt() {
+            Ok(message) => message,
+            Err(()) => break
+        };
+    }
+
+    drop(input);
+    process.set_timeout(Some(1000));
+    if process.wait().is_err() {
+        let _ = process.signal_kill();
+    }
+    failure
+}
+
+/// Runs `program` with `args` as a bot, passing it `seed`. Every game is
+/// played by a new process, started when the game's first state arrives.
+pub fn external(program: String, args: Vec<String>, seed: u64) -> Behaviour {
+    Behaviour::make(proc(state_receiver, action_sender) {
+        loop {
+            let first = match state_receiver.recv_opt() {
+                Ok(message) => message,
+                Err(()) => break
+            };
+            let game_over = {
+                let (_, _, ref game, _) = first;
+                game.is_over()
+            };
+            if game_over {
+                continue;
+            }
+            match play_game(&program, args.as_slice(), seed, first, &state_receiver, &action_sender) {
+                Some(reason) => fail!("{}", reason),
+                None => {}
+            }
+        }
+    })
+}
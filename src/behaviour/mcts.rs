@@ -0,0 +1,246 @@
+//! Monte Carlo tree search with UCT.
+//!
+//! Positions are judged only by playing random games from them to the end,
+//! so the bot needs no heuristic and handles any number of players: every
+//! node keeps the total reward of each player and each player picks the
+//! children that are best for itself.
+//!
+//! When pondering, the bot keeps growing its tree during the other players'
+//! turns, starting over whenever they play a move it did not explore.
+
+use game::{Action, MoveForward, TurnLeft, TurnRight, Bot, Behaviour, GameState, PlayerIndex};
+use game::Simultaneous;
+use std::mem;
+use std::rand::Rng;
+use time::precise_time_ns;
+use util::{seeded_rng, search_budget, GameRng};
+use bitboard::Bitboard;
+
+/// Weight of exploring rarely visited moves against exploiting good ones.
+static EXPLORATION: f64 = 1.4;
+
+/// Iterations run between checks for a new state while pondering.
+static PONDER_ITERATIONS: uint = 64;
+
+/// Visits after which pondering stops growing the tree, to bound memory.
+static MAX_PONDER_VISITS: uint = 500000;
+
+/// Moves of the player to move that do not crash right away, or just
+/// moving forward if every move crashes.
+fn safe_actions(game: &GameState) -> Vec<Action> {
+    let player = &game.players[game.current_player()];
+    let mut actions = vec![];
+    if game.can_move(player.position, player.direction) {
+        actions.push(MoveForward);
+    }
+    if game.can_move(player.position, player.direction.left()) {
+        actions.push(TurnLeft);
+    }
+    if game.can_move(player.position, player.direction.right()) {
+        actions.push(TurnRight);
+    }
+    if actions.is_empty() {
+        actions.push(MoveForward);
+    }
+    actions
+}
+
+/// Reward of each player in a finished game: the winners share one point.
+fn rewards(winners: &[PlayerIndex], player_count: uint) -> Vec<f64> {
+    let mut rewards = Vec::from_elem(player_count, 0.0);
+    for &winner in winners.iter() {
+        *rewards.get_mut(winner) = 1.0 / winners.len() as f64;
+    }
+    rewards
+}
+
+/// Plays random safe moves until the game is over.
+fn playout(game: &GameState, rng: &mut GameRng) -> Vec<f64> {
+    let mut board = Bitboard::from_game(game);
+    let mut actions = Vec::with_capacity(3);
+    while !board.is_over() {
+        let player = board.to_move;
+        let (head, direction) = (board.heads[player], board.directions[player]);
+        actions.clear();
+        for &action in [MoveForward, TurnLeft, TurnRight].iter() {
+            if board.free_step(head, action.apply_to(direction)).is_some() {
+                actions.push(action);
+            }
+        }
+        let action = if actions.is_empty() { MoveForward } else { actions[rng.gen_range(0, actions.len())] };
+        board.make(action);
+    }
+    rewards(board.winners().as_slice(), board.heads.len())
+}
+
+struct Node {
+    action: Action, // Action that led here.
+    game: GameState, // Game state after the action.
+    visits: uint,
+    rewards: Vec<f64>, // Total reward of each player over all visits.
+    untried: Vec<Action>, // Actions without a child yet.
+    children: Vec<Node>
+}
+
+impl Node {
+    fn new(action: Action, game: GameState) -> Node {
+        let untried = if game.is_over() { vec![] } else { safe_actions(&game) };
+        Node {
+            action: action,
+            visits: 0,
+            rewards: Vec::from_elem(game.players.len(), 0.0),
+            untried: untried,
+            game: game,
+            children: vec![]
+        }
+    }
+
+    fn record(&mut self, rewards: &[f64]) {
+        self.visits += 1;
+        for (total, reward) in self.rewards.mut_iter().zip(rewards.iter()) {
+            *total += *reward;
+        }
+    }
+
+    fn mean_reward(&self, player: PlayerIndex) -> f64 {
+        self.rewards[player] / self.visits as f64
+    }
+
+    /// Runs one iteration of the search below this node: select children
+    /// by UCT, expand one untried action, play it out and back up the
+    /// rewards. Returns the rewards of the playout.
+    fn iterate(&mut self, rng: &mut GameRng) -> Vec<f64> {
+        let result = if self.game.is_over() {
+            rewards(self.game.winners().as_slice(), self.game.players.len())
+        } else if !self.untried.is_empty() {
+            let i = rng.gen_range(0, self.untried.len());
+            let action = self.untried.swap_remove(i).unwrap();
+            let mut child = Node::new(action, self.game.apply_action(action));
+            let result = playout(&child.game, rng);
+            child.record(result.as_slice());
+            self.children.push(child);
+            result
+        } else {
+            let mover = self.game.current_player();
+            let log_visits = (self.visits as f64).ln();
+            let mut best = 0;
+            let mut best_value = -1.0;
+            for (i, child) in self.children.iter().enumerate() {
+                let value = child.mean_reward(mover) + EXPLORATION * (log_visits / child.visits as f64).sqrt();
+                if value > best_value {
+                    best = i;
+                    best_value = value;
+                }
+            }
+            self.children.get_mut(best).iterate(rng)
+        };
+        self.record(result.as_slice());
+        result
+    }
+}
+
+/// The node for `game` among `node` and its descendants.
+fn find_subtree(node: Node, game: &GameState) -> Option<Node> {
+    if node.game.hash == game.hash && node.game == *game {
+        return Some(node);
+    }
+    if node.game.turn >= game.turn {
+        return None;
+    }
+    for child in node.children.move_iter() {
+        match find_subtree(child, game) {
+            Some(found) => return Some(found),
+            None => {}
+        }
+    }
+    None
+}
+
+pub struct Mcts {
+    root: Option<Node>, // Subtree of the move chosen last turn.
+    rng: GameRng,
+    ponder: bool
+}
+
+impl Mcts {
+    pub fn new(seed: u64, ponder: bool) -> Mcts {
+        Mcts {
+            root: None,
+            rng: seeded_rng(seed),
+            ponder: ponder
+        }
+    }
+}
+
+impl Bot for Mcts {
+    fn new_game(&mut self, _: PlayerIndex, _: &GameState) {
+        self.root = None;
+    }
+
+    fn act(&mut self, me: PlayerIndex, game: &GameState, deadline: u64) -> Action {
+        let deadline = precise_time_ns() + search_budget(deadline);
+        let game = game.sequential_view(me);
+        let previous = mem::replace(&mut self.root, None);
+        let mut root = match previous.and_then(|node| find_subtree(node, &game)) {
+            Some(node) => {
+                debug!("Reusing {} visits", node.visits);
+                node
+            }
+            None => Node::new(MoveForward, game)
+        };
+
+        let reused = root.visits;
+        while precise_time_ns() < deadline {
+            root.iterate(&mut self.rng);
+        }
+        debug!("{} new iterations, {} visits in total", root.visits - reused, root.visits);
+        for child in root.children.iter() {
+            debug!("{}: {} visits, mean reward {}", child.action, child.visits, child.mean_reward(me));
+        }
+
+        let best = match root.children.iter().enumerate().max_by(|&(_, child)| child.visits) {
+            Some((i, _)) => i,
+            None => return MoveForward
+        };
+        let chosen = root.children.swap_remove(best).unwrap();
+        let action = chosen.action;
+        self.root = Some(chosen);
+        action
+    }
+
+    fn ponders(&self) -> bool {
+        self.ponder
+    }
+
+    fn ponder(&mut self, me: PlayerIndex, game: &GameState) -> bool {
+        let game = if game.rules == Simultaneous { game.sequential_view(me) } else { game.clone() };
+        let mut root = match self.root.take() {
+            Some(root) => if root.game.turn > game.turn {
+                // Still the state we last moved in; think about the move we chose.
+                root
+            } else {
+                match find_subtree(root, &game) {
+                    Some(node) => node,
+                    None => {
+                        debug!("Pondering missed turn {}, starting over", game.turn);
+                        Node::new(MoveForward, game)
+                    }
+                }
+            },
+            None => Node::new(MoveForward, game)
+        };
+        if root.game.is_over() || root.visits >= MAX_PONDER_VISITS {
+            self.root = Some(root);
+            return false;
+        }
+        for _ in range(0, PONDER_ITERATIONS) {
+            root.iterate(&mut self.rng);
+        }
+        self.root = Some(root);
+        true
+    }
+}
+
+pub fn mcts(seed: u64, ponder: bool) -> Behaviour {
+    Behaviour::from_bot(Mcts::new(seed, ponder))
+}
@@ -1,18 +1,40 @@
-use game::{Action, MoveForward, TurnLeft, TurnRight, Behaviour, GameState, Position, PlayerIndex};
+use game::{Action, MoveForward, TurnLeft, TurnRight, Bot, Behaviour, GameState, Position, PlayerIndex};
+use bitboard::Bitboard;
 use std::f64;
+use std::u64;
 use time::precise_time_ns;
 use std::cmp::max;
-use util::{random_bernoulli, flood_count};
+use game::{North, East, South, West};
+use util::{Evaluation, search_budget};
+use endgame;
+use transposition::{TranspositionTable, Entry, Exact, LowerBound, UpperBound};
 
-static TARGET_ACT_TIME : u64 = 50000000;
+/// Score of a won game. Wins and losses are adjusted by the number of plies
+/// it takes to reach them, so that quick wins and slow losses are preferred.
+static WIN_SCORE: f64 = 1e9;
 
-fn explore_probability(depth: uint, falloff: f64) -> f64 {
-    let d = depth as f64;
-    if d <= falloff {
-        1.0
+/// Entries in each bot's transposition table.
+static TABLE_SIZE: uint = 1 << 16;
+
+/// Win and loss scores depend on the distance from the root, so the table
+/// stores them relative to the node instead.
+fn to_table(score: f64, ply: uint) -> f64 {
+    if score > WIN_SCORE / 2.0 {
+        score + ply as f64
+    } else if score < -WIN_SCORE / 2.0 {
+        score - ply as f64
+    } else {
+        score
+    }
+}
+
+fn from_table(score: f64, ply: uint) -> f64 {
+    if score > WIN_SCORE / 2.0 {
+        score - ply as f64
+    } else if score < -WIN_SCORE / 2.0 {
+        score + ply as f64
     } else {
-        1.0 - 1.0 / (1.0
-                     + (falloff * 2.0 - d.powf(1.0/1.1)).exp())
+        score
     }
 }
 
@@ -24,71 +46,220 @@ fn position_distance(a: Position, b: Position) -> int {
 
 static ACTIONS: [Action, ..3] = [MoveForward, TurnLeft, TurnRight];
 
-fn explore(player: PlayerIndex, game: &GameState, depth: uint, minimize: bool, start_time: u64) -> f64 {
-    if game.status.is_over() {
-        if game.winner() == player {
-            return f64::INFINITY;
-        } else {
-            return -f64::INFINITY;
-        }
+/// Compares the space we can still reach with the opponents' space.
+pub fn space_evaluation(board: &Bitboard, player: PlayerIndex) -> f64 {
+    let our_head = board.heads[player];
+    let opponents = board.opponents(player);
+    let our_space = board.flood_count(our_head) as f64;
+    let their_space = opponents.iter().map(|&other| {
+        board.flood_count(board.heads[other]) as f64
+    }).fold(0.0, |a, b| if a > b { a } else { b });
+    let distance = opponents.iter().map(|&other| {
+        position_distance(board.position(our_head), board.position(board.heads[other]))
+    }).min().unwrap_or(1);
+    if our_space > their_space {
+        100.0 * our_space
+    } else if our_space < their_space {
+        -1000.0 / our_space
+    } else {
+        our_space / distance as f64
     }
-    let time_progress = ((precise_time_ns() - start_time) as f64) / (TARGET_ACT_TIME as f64);
-    if time_progress > 0.9 || !random_bernoulli(explore_probability(depth, (1.0 - time_progress) * 9.0)) {
-        let our_pos = game.players[player].position;
-        let other_player = game.player_after(player);
-        let their_pos = game.players[other_player].position;
-        let our_space = flood_count(our_pos, game) as f64;
-        let their_space = flood_count(their_pos, game) as f64;
-        if our_space > their_space {
-            return 100.0 * our_space;
-        } else if our_space < their_space {
-            return -1000.0 / our_space;
-        } else {
-            return our_space / position_distance(our_pos, their_pos) as f64;
+}
+
+struct Search<'a> {
+    player: PlayerIndex,
+    evaluation: Evaluation,
+    table: &'a mut TranspositionTable,
+    deadline: u64, // precise_time_ns after which the search is abandoned.
+    nodes: uint,
+    depth_limited: bool // Whether any line was cut short by the depth limit.
+}
+
+impl<'a> Search<'a> {
+    /// Paranoid alpha-beta search: every opponent is assumed to play against
+    /// us, so with more than two players we minimize on all turns but our
+    /// own. Returns `None` if the deadline passed.
+    fn alpha_beta(&mut self, board: &mut Bitboard, depth: uint, ply: uint, mut alpha: f64, mut beta: f64) -> Option<f64> {
+        self.nodes += 1;
+        if self.nodes % 64 == 0 && precise_time_ns() > self.deadline {
+            return None;
+        }
+
+        if board.is_over() {
+            let winners = board.winners();
+            return Some(if !winners.contains(&self.player) {
+                -(WIN_SCORE - ply as f64)
+            } else if winners.len() == 1 {
+                WIN_SCORE - ply as f64
+            } else {
+                0.0 // A shared first place beats losing but not winning outright.
+            });
+        }
+        if !board.alive[self.player] {
+            return Some(-(WIN_SCORE - ply as f64));
+        }
+        if depth == 0 {
+            self.depth_limited = true;
+            let head = board.heads[self.player];
+            if [North, East, South, West].iter().all(|&d| board.free_step(head, d).is_none()) {
+                // Boxed in: we crash on our next move.
+                return Some(-(WIN_SCORE - (ply + 1) as f64));
+            }
+            return Some((self.evaluation)(&*board, self.player));
+        }
+
+        let (original_alpha, original_beta) = (alpha, beta);
+        let mut actions = ACTIONS.to_vec();
+        match self.table.probe(board.hash) {
+            Some(entry) => {
+                if entry.depth >= depth {
+                    // The stored result may itself have been cut short.
+                    self.depth_limited = true;
+                    let score = from_table(entry.score, ply);
+                    match entry.bound {
+                        Exact => return Some(score),
+                        LowerBound => if score > alpha { alpha = score; },
+                        UpperBound => if score < beta { beta = score; }
+                    }
+                    if alpha >= beta {
+                        return Some(score);
+                    }
+                }
+                // Try the move that was best last time first.
+                match entry.best_action {
+                    Some(action) => {
+                        actions.retain(|&a| a != action);
+                        actions.insert(0, action);
+                    }
+                    None => {}
+                }
+            }
+            None => {}
+        }
+
+        let maximize = board.to_move == self.player;
+        let mut best = if maximize { -f64::INFINITY } else { f64::INFINITY };
+        let mut best_action = None;
+        for action in actions.iter() {
+            let undo = board.make(*action);
+            let result = self.alpha_beta(board, depth - 1, ply + 1, alpha, beta);
+            board.unmake(undo);
+            let score = match result {
+                Some(score) => score,
+                None => return None
+            };
+            if maximize {
+                if score > best { best = score; best_action = Some(*action); }
+                if best > alpha { alpha = best; }
+            } else {
+                if score < best { best = score; best_action = Some(*action); }
+                if best < beta { beta = best; }
+            }
+            if alpha >= beta {
+                break;
+            }
         }
+
+        self.table.store(Entry {
+            hash: board.hash,
+            depth: depth,
+            score: to_table(best, ply),
+            bound: if best <= original_alpha {
+                UpperBound
+            } else if best >= original_beta {
+                LowerBound
+            } else {
+                Exact
+            },
+            best_action: best_action
+        });
+        Some(best)
     }
-    let init = if minimize { f64::INFINITY } else { -f64::INFINITY };
-    let foldfn = if minimize {
-        |a: f64, b: f64| if a < b { a } else { b }
-    } else {
-        |a: f64, b: f64| if a > b { a } else { b }
-    };
-    ACTIONS.iter().map(|action| {
-        let new_game = game.apply_action(*action);
-        explore(player, &new_game, depth + 1, !minimize, start_time)
-    }).fold(init, foldfn)
 }
 
-fn act(game: &GameState) -> Action {
-    let player_index = game.current_player();
-
-    let (best_action, _) = ACTIONS.iter().map(|action| {
-        let new_game = game.apply_action(*action);
-        let score = explore(player_index, &new_game, 0, true, precise_time_ns());
-        (*action, score)
-    }).fold((MoveForward, -f64::INFINITY), |(xa, xs), (ya, ys)|
-        if xs > ys {
-            (xa, xs)
-        } else {
-            (ya, ys)
+/// Searches ever deeper until `time_budget` nanoseconds have passed, and
+/// returns the best move of the deepest search that finished. Each
+/// iteration tries the moves in the order of the previous one's scores.
+fn act(player_index: PlayerIndex, game: &GameState, time_budget: u64, table: &mut TranspositionTable,
+       evaluation: Evaluation) -> Action {
+    let deadline = precise_time_ns() + time_budget;
+    match endgame::separated_action(game, player_index, deadline) {
+        Some(action) => return action,
+        None => {}
+    }
+    let mut board = Bitboard::from_game(&game.sequential_view(player_index));
+
+    let mut order: Vec<(Action, f64)> = ACTIONS.iter().map(|&action| (action, 0.0)).collect();
+    let mut depth = 1u;
+    loop {
+        let mut search = Search {
+            player: player_index,
+            evaluation: evaluation,
+            table: &mut *table,
+            // Always finish the first iteration so that there is a move.
+            deadline: if depth == 1 { u64::MAX } else { deadline },
+            nodes: 0,
+            depth_limited: false
+        };
+        let mut alpha = -f64::INFINITY;
+        let mut scores = vec![];
+        for &(action, _) in order.iter() {
+            let undo = board.make(action);
+            let result = search.alpha_beta(&mut board, depth - 1, 1, alpha, f64::INFINITY);
+            board.unmake(undo);
+            match result {
+                Some(score) => {
+                    scores.push((action, score));
+                    if score > alpha { alpha = score; }
+                }
+                None => break
+            }
+        }
+        if scores.len() < order.len() {
+            debug!("Depth {} ran out of time after {} nodes", depth, search.nodes);
+            break;
         }
-    );
 
+        // The sort is stable, so equally scored moves keep their order.
+        scores.sort_by(|&(_, a), &(_, b)| b.partial_cmp(&a).unwrap());
+        order = scores;
+        let (_, best_score) = order[0];
+        debug!("Depth {}: {} nodes, best {}, table hits {}", depth, search.nodes, order[0], search.table.hits);
+        if !search.depth_limited || best_score.abs() > WIN_SCORE / 2.0 {
+            // Nothing more to learn by searching deeper.
+            break;
+        }
+        depth += 1;
+    }
+
+    let (best_action, _) = order[0];
     best_action
 }
 
-pub fn minimax() -> Behaviour {
-    Behaviour::make(proc(state_receiver, action_sender) {
-        loop {
-            let game = state_receiver.recv();
-            if game.is_over() {
-                debug!("Game is over, quitting.");
-                break;
-            };
-            let action = act(&game);
-            debug!("Sending action {}", action);
-            action_sender.send((game.turn, action));
+pub struct Minimax {
+    table: TranspositionTable,
+    evaluation: Evaluation // Scores the positions at the search horizon.
+}
+
+impl Minimax {
+    pub fn new(evaluation: Evaluation) -> Minimax {
+        Minimax {
+            table: TranspositionTable::new(TABLE_SIZE),
+            evaluation: evaluation
         }
-    })
+    }
+}
+
+impl Bot for Minimax {
+    fn new_game(&mut self, _: PlayerIndex, _: &GameState) {
+        self.table.clear();
+    }
+
+    fn act(&mut self, me: PlayerIndex, game: &GameState, deadline: u64) -> Action {
+        act(me, game, search_budget(deadline), &mut self.table, self.evaluation)
+    }
 }
 
+pub fn minimax(evaluation: Evaluation) -> Behaviour {
+    Behaviour::from_bot(Minimax::new(evaluation))
+}
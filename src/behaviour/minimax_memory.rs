@@ -1,41 +1,12 @@
-extern crate ncurses;
-
-use game::{Action, MoveForward, TurnLeft, TurnRight, Behaviour, GameState, Position, PlayerIndex};
-use game::{North, East, South, West};
-use behaviour::static_action::StaticAction;
-use std::vec::Vec;
-use std::owned::Box;
+use game::{Action, MoveForward, TurnLeft, TurnRight, Bot, Behaviour, GameState, Position, PlayerIndex};
+use std::f64;
+use std::mem;
 use std::cmp::max;
-use std::rand::random;
 use time::precise_time_ns;
-
-fn flood_count(position: Position, game: &GameState) -> uint {
-    let mut flooded = Vec::from_elem(game.board_height, Vec::from_elem(game.board_width, false));
-    let mut count = 0u;
-    fn fill(pos: Position, game: &GameState, flooded: &mut Vec<Vec<bool>>, count: &mut uint) {
-        let (r, c) = pos;
-        if game.can_move_to(pos) && !(*flooded)[r][c] {
-            flooded.get_mut(r).grow_set(c, &false, true);
-            *count += 1;
-            fill(North.apply_to(pos), game, flooded, count);
-            fill(East.apply_to(pos), game, flooded, count);
-            fill(South.apply_to(pos), game, flooded, count);
-            fill(West.apply_to(pos), game, flooded, count);
-        }
-    };
-    fill(North.apply_to(position), game, &mut flooded, &mut count);
-    fill(East.apply_to(position), game, &mut flooded, &mut count);
-    fill(South.apply_to(position), game, &mut flooded, &mut count);
-    fill(West.apply_to(position), game, &mut flooded, &mut count);
-    count
-}
-
-fn game_apply_action(game: &GameState, action: Action) -> GameState {
-    let mut new_game = game.clone();
-    let mut behaviours = Vec::from_fn(game.players.len(), |_| StaticAction::new(action));
-    new_game.do_turn(behaviours.as_mut_slice());
-    new_game
-}
+use util::{random_bernoulli, seeded_rng, search_budget, GameRng, Evaluation};
+use bitboard::Bitboard;
+use endgame;
+use transposition::{TranspositionTable, Entry, Exact};
 
 fn position_distance(a: Position, b: Position) -> int {
     let (ar, ac) = a;
@@ -43,29 +14,44 @@ fn position_distance(a: Position, b: Position) -> int {
     max((ar as int - br as int).abs(), (ac as int - bc as int).abs())
 }
 
-fn state_score(game: &GameState, player: PlayerIndex) -> f64 {
-    let mut score: f64 = 0.0;
-
+fn state_score(game: &GameState, player: PlayerIndex, evaluation: Evaluation) -> f64 {
+    if !game.is_over() && !game.players[player].is_alive {
+        return -100.0;
+    }
+    let score = evaluation(&Bitboard::from_game(game), player);
     if game.is_over() {
-        if game.winner() == player {
-            score += 100.0;
-        } else {
-            score -= 100.0;
+        let winners = game.winners();
+        if !winners.contains(&player) {
+            return score - 100.0;
+        } else if winners.len() == 1 {
+            return score + 100.0;
         }
     }
+    score
+}
 
-    let our_pos = game.players[player].position;
-    let other_player = game.player_after(player);
-    let their_pos = game.players[other_player].position;
-    let board_size = (game.board_width * game.board_height) as f64;
-    let our_space = flood_count(our_pos, game) as f64 / board_size;
-    let their_space = flood_count(their_pos, game) as f64 / board_size;
+/// Compares the share of the board we can still reach with the opponents'.
+pub fn space_evaluation(board: &Bitboard, player: PlayerIndex) -> f64 {
+    let mut score: f64 = 0.0;
+    let our_head = board.heads[player];
+    let opponents = board.opponents(player);
+    if opponents.is_empty() {
+        return score;
+    }
+    let board_size = (board.width * board.height) as f64;
+    let our_space = board.flood_count(our_head) as f64 / board_size;
+    let their_space = opponents.iter().map(|&other| {
+        board.flood_count(board.heads[other]) as f64 / board_size
+    }).fold(0.0, |a, b| if a > b { a } else { b });
+    let distance = opponents.iter().map(|&other| {
+        position_distance(board.position(our_head), board.position(board.heads[other]))
+    }).min().unwrap();
     if our_space > their_space {
         score += 1.0 + our_space;
     } else if our_space < their_space {
         score -= 1.0 / our_space
     } else {
-        score += our_space / position_distance(our_pos, their_pos) as f64;
+        score += our_space / distance as f64;
     }
 
     score
@@ -76,34 +62,32 @@ struct GameNode {
     action: Action,
     player: PlayerIndex, // Player that is taking the action.
     game: GameState, // Game state after the action
-    score: f64, // Score of the game state.
+    score: f64, // Score of the game state for the searching player.
     children: Vec<GameNode> // Explored sub-GameNodes.
 }
 
 static ACTIONS: [Action, ..3] = [MoveForward, TurnLeft, TurnRight];
 
-static TARGET_ACT_TIME : u64 = 50000000;
+/// Entries in the cache of state scores.
+static TABLE_SIZE: uint = 1 << 16;
 
-fn random_bernoulli(p: f64) -> bool {
-    let x: f64 = random();
-    x < p
-}
-
-fn explore_tree(game: &GameState, tree: &mut Vec<GameNode>, start_time: u64, depth: uint) {
+fn explore_tree(game: &GameState, tree: &mut Vec<GameNode>, me: PlayerIndex, start_time: u64, budget: u64,
+                depth: uint, rng: &mut GameRng, table: &mut TranspositionTable, evaluation: Evaluation) {
     if game.is_over() {
         return;
     }
 
     if tree.is_empty() {
         *tree = ACTIONS.iter().map(|&action| {
-            GameNode::new(game, action)
+            GameNode::new(game, action, me, table, evaluation)
         }).collect();
     }
 
-    let time_progress = ((precise_time_ns() - start_time) as f64) / (TARGET_ACT_TIME as f64);
-    if time_progress < 1.0 && random_bernoulli(1.3f64.powf(-time_progress * (depth as f64))) {
+    let time_progress = ((precise_time_ns() - start_time) as f64) / (budget as f64);
+    if time_progress < 1.0 && random_bernoulli(rng, 1.3f64.powf(-time_progress * (depth as f64))) {
         for node in tree.mut_iter() {
-            explore_tree(&node.game, &mut node.children, start_time, depth + 1);
+            explore_tree(&node.game, &mut node.children, me, start_time, budget, depth + 1, rng, table,
+                         evaluation);
         }
     } else {
         debug!("Stopping exploration at depth {}", depth);
@@ -111,61 +95,126 @@ fn explore_tree(game: &GameState, tree: &mut Vec<GameNode>, start_time: u64, dep
 }
 
 impl GameNode {
-    pub fn new(game: &GameState, action: Action) -> GameNode {
-        let new_game = game_apply_action(game, action);
-        let player = game.current_player();
+    pub fn new(game: &GameState, action: Action, me: PlayerIndex, table: &mut TranspositionTable,
+               evaluation: Evaluation) -> GameNode {
+        let new_game = game.apply_action(action);
+        // Different move orders often lead to the same state.
+        let score = match table.probe(new_game.hash) {
+            Some(entry) => entry.score,
+            None => {
+                let score = state_score(&new_game, me, evaluation);
+                table.store(Entry {
+                    hash: new_game.hash,
+                    depth: 0,
+                    score: score,
+                    bound: Exact,
+                    best_action: None
+                });
+                score
+            }
+        };
         GameNode {
             action: action,
-            player: player,
-            score: state_score(&new_game, player),
+            player: game.current_player(),
+            score: score,
             game: new_game,
             children: vec!()
         }
     }
+
+    /// Score of the node for `me` backed up from the explored leaves,
+    /// assuming every opponent plays against us.
+    fn minimax_score(&self, me: PlayerIndex) -> f64 {
+        if self.children.is_empty() {
+            return self.score;
+        }
+        let scores = self.children.iter().map(|child| child.minimax_score(me));
+        if self.game.current_player() == me {
+            scores.fold(-f64::INFINITY, |a, b| if a > b { a } else { b })
+        } else {
+            scores.fold(f64::INFINITY, |a, b| if a < b { a } else { b })
+        }
+    }
 }
 
+/// Finds the explored continuations of `game` among `nodes` and their
+/// descendants, so that the search can pick up where it left off.
+fn find_subtree(nodes: Vec<GameNode>, game: &GameState) -> Option<Vec<GameNode>> {
+    for node in nodes.move_iter() {
+        if node.game == *game {
+            return Some(node.children);
+        }
+        if node.game.turn < game.turn {
+            match find_subtree(node.children, game) {
+                Some(children) => return Some(children),
+                None => {}
+            }
+        }
+    }
+    None
+}
 
-#[deriving(Show)]
 pub struct MinimaxMemory {
     tree: Vec<GameNode>,
-    chosen_tree: Vec<GameNode>
+    chosen_tree: Vec<GameNode>,
+    rng: GameRng,
+    table: TranspositionTable,
+    evaluation: Evaluation
 }
 
 impl MinimaxMemory {
-    pub fn new() -> Box<Behaviour> {
-        box MinimaxMemory { 
+    pub fn new(seed: u64, evaluation: Evaluation) -> MinimaxMemory {
+        MinimaxMemory {
             tree: vec!(),
-            chosen_tree: vec!()
-        } as Box<Behaviour>
+            chosen_tree: vec!(),
+            rng: seeded_rng(seed),
+            table: TranspositionTable::new(TABLE_SIZE),
+            evaluation: evaluation
+        }
     }
 }
 
-impl Behaviour for MinimaxMemory {
-    fn act(&mut self, game: &GameState) -> Action {
-        if !self.chosen_tree.is_empty() {
-            for node in self.chosen_tree.iter() {
-                if node.game == *game {
-                    self.tree = node.children.clone();
-                    break
-                }
-            };
-        }
+impl Bot for MinimaxMemory {
+    fn new_game(&mut self, _: PlayerIndex, _: &GameState) {
+        self.chosen_tree = vec!();
+        self.table.clear();
+    }
 
+    fn act(&mut self, me: PlayerIndex, game: &GameState, deadline: u64) -> Action {
         let start_time = precise_time_ns();
-        explore_tree(game, &mut self.tree, start_time, 0);
-
-        let tree = self.tree.clone();
-        let best_node = tree.iter().fold(&tree[0], |a, b|
-            if a.score > b.score {
-                a
-            } else {
-                b
+        let budget = search_budget(deadline);
+        match endgame::separated_action(game, me, start_time + budget) {
+            Some(action) => {
+                self.chosen_tree = vec!();
+                return action;
             }
-        );
+            None => {}
+        }
+        let game = game.sequential_view(me);
+        let previous = mem::replace(&mut self.chosen_tree, vec!());
+        self.tree = find_subtree(previous, &game).unwrap_or(vec!());
+        if !self.tree.is_empty() {
+            debug!("Reusing {} explored moves", self.tree.len());
+        }
 
-        self.chosen_tree = best_node.children.clone();
-        self.tree.clear();
-                
+        explore_tree(&game, &mut self.tree, me, start_time, budget, 0, &mut self.rng, &mut self.table,
+                     self.evaluation);
+
+        let tree = mem::replace(&mut self.tree, vec!());
+        let scores: Vec<f64> = tree.iter().map(|node| node.minimax_score(me)).collect();
+        let mut best = 0;
+        for i in range(1, tree.len()) {
+            if scores[i] > scores[best] {
+                best = i;
+            }
+        }
+
+        let best_node = tree.move_iter().nth(best).unwrap();
+        self.chosen_tree = best_node.children;
         best_node.action
     }
 }
+
+pub fn minimax_memory(seed: u64, evaluation: Evaluation) -> Behaviour {
+    Behaviour::from_bot(MinimaxMemory::new(seed, evaluation))
+}
@@ -1,15 +1,15 @@
-use game::{Action, Behaviour};
+use game::{Action, Bot, Behaviour, GameState, PlayerIndex};
+
+pub struct StaticAction {
+    action: Action
+}
+
+impl Bot for StaticAction {
+    fn act(&mut self, _: PlayerIndex, _: &GameState, _: u64) -> Action {
+        self.action
+    }
+}
 
 pub fn static_action(action: Action) -> Behaviour {
-    Behaviour::make(proc(state_receiver, action_sender) {
-        loop {
-            let game = state_receiver.recv();
-            if game.is_over() {
-                debug!("Game is over, quitting.");
-                break;
-            };
-            debug!("Sending action {}", action);
-            action_sender.send((game.turn, action));
-        }
-    })
+    Behaviour::from_bot(StaticAction { action: action })
 }
@@ -1,53 +1,54 @@
-use game::{Action, MoveForward, TurnLeft, TurnRight, Behaviour, GameState};
-use util::random_bernoulli;
+use game::{Action, MoveForward, TurnLeft, TurnRight, Bot, Behaviour, GameState, PlayerIndex};
+use util::{random_bernoulli, seeded_rng, GameRng};
 
-fn random_turn() -> Action {
-    if random_bernoulli(0.5) {
+fn random_turn(rng: &mut GameRng) -> Action {
+    if random_bernoulli(rng, 0.5) {
         TurnLeft
     } else {
         TurnRight
     }
 }
 
-pub fn stupid_random(stability: f64) -> Behaviour {
-    Behaviour::make(proc(state_receiver, action_sender) {
-        let mut turns_since_change: uint = 0;
+pub struct StupidRandom {
+    stability: f64, // Mean number of moves between random turns.
+    turns_since_change: uint,
+    rng: GameRng
+}
+
+impl StupidRandom {
+    pub fn new(stability: f64, seed: u64) -> StupidRandom {
+        StupidRandom {
+            stability: stability,
+            turns_since_change: 0,
+            rng: seeded_rng(seed)
+        }
+    }
+}
 
-        let act = |game: &GameState| {
-            let player_index = game.current_player();
-            let player = &game.players[player_index];
-            let forward_pos = player.direction.apply_to(player.position);
-            let left_pos = player.direction.left().apply_to(player.position);
-            let right_pos = player.direction.right().apply_to(player.position);
-            let forward_free = game.can_move_to(forward_pos);
-            let left_free = game.can_move_to(left_pos);
-            let right_free = game.can_move_to(right_pos);
-            let change_probability = 1f64 - (-(turns_since_change as f64) / stability).exp();
+impl Bot for StupidRandom {
+    fn act(&mut self, player_index: PlayerIndex, game: &GameState, _: u64) -> Action {
+        let player = &game.players[player_index];
+        let forward_free = game.can_move(player.position, player.direction);
+        let left_free = game.can_move(player.position, player.direction.left());
+        let right_free = game.can_move(player.position, player.direction.right());
+        let change_probability = 1f64 - (-(self.turns_since_change as f64) / self.stability).exp();
 
-            if forward_free && ((!left_free && !right_free) || !random_bernoulli(change_probability)) {
-                turns_since_change += 1;
-                MoveForward
+        if forward_free && ((!left_free && !right_free) || !random_bernoulli(&mut self.rng, change_probability)) {
+            self.turns_since_change += 1;
+            MoveForward
+        } else {
+            self.turns_since_change = 0;
+            if !left_free {
+                TurnRight
+            } else if !right_free {
+                TurnLeft
             } else {
-                turns_since_change = 0;
-                if !left_free {
-                    TurnRight
-                } else if !right_free {
-                    TurnLeft
-                } else {
-                    random_turn()
-                }
+                random_turn(&mut self.rng)
             }
-        };
-
-        loop {
-            let game = state_receiver.recv();
-            if game.is_over() {
-                debug!("Game is over, quitting.");
-                break;
-            };
-            let action = act(&game);
-            debug!("Sending action {}", action);
-            action_sender.send((game.turn, action));
         }
-    })
+    }
+}
+
+pub fn stupid_random(stability: f64, seed: u64) -> Behaviour {
+    Behaviour::from_bot(StupidRandom::new(stability, seed))
 }
@@ -0,0 +1,313 @@
+//! Compact game state for search.
+//!
+//! `GameState` keeps a grid of tiles and search clones all of it for every
+//! position it looks at. Search only needs to know which cells are blocked
+//! and where the heads are, so `Bitboard` keeps one bit per cell and plays
+//! moves in place: `make` plays a move and returns what `unmake` needs to
+//! take it back. Only alternating play is supported; simultaneous games are
+//! searched through `GameState::sequential_view`.
+
+use game::{Action, Direction, North, East, South, West, GameState, Position, PlayerIndex, Topology};
+use game::{Crash, PlayerHead, PlayerTurn};
+use zobrist;
+
+#[deriving(Clone, Show)]
+pub struct Bitboard {
+    pub width: uint,
+    pub height: uint,
+    pub topology: Topology,
+    blocked: Vec<u64>, // One bit per cell, row by row, set if the cell is not passable.
+    pub heads: Vec<uint>, // Cell of each player's head.
+    pub directions: Vec<Direction>,
+    pub alive: Vec<bool>,
+    pub alive_count: uint,
+    pub to_move: PlayerIndex,
+    pub turn: uint,
+    pub turn_limit: Option<uint>,
+    pub hash: u64 // Zobrist hash of the blocked cells, heads, directions and player to move.
+}
+
+/// What `Bitboard::unmake` needs to take a move back.
+pub struct Undo {
+    player: PlayerIndex,
+    head: uint,
+    direction: Direction,
+    crashed: bool,
+    hash: u64
+}
+
+impl Bitboard {
+    pub fn from_game(game: &GameState) -> Bitboard {
+        let (width, height) = (game.board_width, game.board_height);
+        let to_move = match game.status {
+            PlayerTurn(p) => p,
+            _ => range(0, game.players.len()).find(|&p| game.players[p].is_alive).unwrap_or(0)
+        };
+        let mut board = Bitboard {
+            width: width,
+            height: height,
+            topology: game.topology.clone(),
+            blocked: Vec::from_elem((width * height + 63) / 64, 0u64),
+            heads: game.players.iter().map(|player| {
+                let (r, c) = player.position;
+                r * width + c
+            }).collect(),
+            directions: game.players.iter().map(|player| player.direction).collect(),
+            alive: game.players.iter().map(|player| player.is_alive).collect(),
+            alive_count: game.alive_count,
+            to_move: to_move,
+            turn: game.turn,
+            turn_limit: game.turn_limit,
+            hash: zobrist::status_key(&PlayerTurn(to_move))
+        };
+        for r in range(0, height) {
+            for c in range(0, width) {
+                if !game.board[r][c].is_passable() {
+                    board.set_blocked(r * width + c, true);
+                    board.hash ^= zobrist::tile_key((r, c), &Crash);
+                }
+            }
+        }
+        for p in range(0, board.heads.len()) {
+            board.hash ^= zobrist::direction_key(p, board.directions[p]);
+            if board.alive[p] {
+                board.hash ^= zobrist::tile_key(board.position(board.heads[p]), &PlayerHead(p));
+            }
+        }
+        board
+    }
+
+    pub fn cell(&self, position: Position) -> uint {
+        let (r, c) = position;
+        r * self.width + c
+    }
+
+    pub fn position(&self, cell: uint) -> Position {
+        (cell / self.width, cell % self.width)
+    }
+
+    pub fn is_free(&self, cell: uint) -> bool {
+        self.blocked[cell / 64] & (1u64 << (cell % 64)) == 0
+    }
+
+    fn set_blocked(&mut self, cell: uint, blocked: bool) {
+        let bit = 1u64 << (cell % 64);
+        let word = self.blocked.get_mut(cell / 64);
+        if blocked { *word |= bit; } else { *word &= !bit; }
+    }
+
+    /// The cell one step from `cell` in `direction`, if it is on the board.
+    pub fn step(&self, cell: uint, direction: Direction) -> Option<uint> {
+        self.topology.step(self.position(cell), direction, self.width, self.height).map(|next| self.cell(next))
+    }
+
+    /// The cell one step from `cell` in `direction`, if a player can move there.
+    pub fn free_step(&self, cell: uint, direction: Direction) -> Option<uint> {
+        match self.step(cell, direction) {
+            Some(next) if self.is_free(next) => Some(next),
+            _ => None
+        }
+    }
+
+    pub fn player_after(&self, current: PlayerIndex) -> PlayerIndex {
+        assert!(self.alive_count >= 1);
+        let mut cur = (current + 1) % self.heads.len();
+        while !self.alive[cur] {
+            cur = (cur + 1) % self.heads.len();
+        }
+        cur
+    }
+
+    pub fn opponents(&self, player: PlayerIndex) -> Vec<PlayerIndex> {
+        range(0, self.heads.len()).filter(|&i| i != player && self.alive[i]).collect()
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.alive_count <= 1 || self.turn_limit.map_or(false, |limit| self.turn >= limit)
+    }
+
+    /// Players sharing first place; empty while the game is in progress.
+    pub fn winners(&self) -> Vec<PlayerIndex> {
+        if !self.is_over() {
+            return vec![];
+        }
+        range(0, self.heads.len()).filter(|&i| self.alive[i]).collect()
+    }
+
+    /// Plays `action` for the player to move, like `GameState::do_turn`.
+    pub fn make(&mut self, action: Action) -> Undo {
+        let player = self.to_move;
+        let head = self.heads[player];
+        let old_direction = self.directions[player];
+        let hash = self.hash;
+
+        let direction = action.apply_to(old_direction);
+        *self.directions.get_mut(player) = direction;
+        self.hash ^= zobrist::direction_key(player, old_direction) ^ zobrist::direction_key(player, direction);
+
+        let crashed = match self.free_step(head, direction) {
+            Some(target) => {
+                self.set_blocked(target, true);
+                *self.heads.get_mut(player) = target;
+                self.hash ^= zobrist::tile_key(self.position(target), &Crash) ^
+                    zobrist::tile_key(self.position(head), &PlayerHead(player)) ^
+                    zobrist::tile_key(self.position(target), &PlayerHead(player));
+                false
+            }
+            None => {
+                *self.alive.get_mut(player) = false;
+                self.alive_count -= 1;
+                self.hash ^= zobrist::tile_key(self.position(head), &PlayerHead(player));
+                true
+            }
+        };
+
+        if self.alive_count >= 1 {
+            let next = self.player_after(player);
+            self.hash ^= zobrist::status_key(&PlayerTurn(player)) ^ zobrist::status_key(&PlayerTurn(next));
+            self.to_move = next;
+        }
+        self.turn += 1;
+
+        Undo {
+            player: player,
+            head: head,
+            direction: old_direction,
+            crashed: crashed,
+            hash: hash
+        }
+    }
+
+    /// Takes back the move `make` returned `undo` for.
+    pub fn unmake(&mut self, undo: Undo) {
+        let player = undo.player;
+        self.turn -= 1;
+        self.to_move = player;
+        if undo.crashed {
+            *self.alive.get_mut(player) = true;
+            self.alive_count += 1;
+        } else {
+            let target = self.heads[player];
+            self.set_blocked(target, false);
+            *self.heads.get_mut(player) = undo.head;
+        }
+        *self.directions.get_mut(player) = undo.direction;
+        self.hash = undo.hash;
+    }
+
+    /// Number of free cells reachable from `cell`, not counting `cell`.
+    pub fn flood_count(&self, cell: uint) -> uint {
+        let mut flooded = Vec::from_elem(self.blocked.len(), 0u64);
+        let mut count = 0u;
+        let mut stack = vec![cell];
+        loop {
+            let cell = match stack.pop() {
+                Some(cell) => cell,
+                None => break
+            };
+            for &direction in [North, East, South, West].iter() {
+                match self.free_step(cell, direction) {
+                    Some(next) if flooded[next / 64] & (1u64 << (next % 64)) == 0 => {
+                        *flooded.get_mut(next / 64) |= 1u64 << (next % 64);
+                        count += 1;
+                        stack.push(next);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod bench {
+    extern crate test;
+
+    use self::test::Bencher;
+    use bitboard::Bitboard;
+    use game::{GameState, Alternating, Action, MoveForward, TurnLeft, TurnRight, default_players};
+    use game::{Position, North, East, South, West};
+
+    static ACTIONS: [Action, ..3] = [MoveForward, TurnLeft, TurnRight];
+
+    static DEPTH: uint = 6;
+
+    fn opening() -> GameState {
+        GameState::new(40, 20, default_players(2, 40, 20), Alternating)
+    }
+
+    fn clone_nodes(game: &GameState, depth: uint) -> uint {
+        if depth == 0 || game.is_over() {
+            return 1;
+        }
+        ACTIONS.iter().fold(1, |nodes, &action| nodes + clone_nodes(&game.apply_action(action), depth - 1))
+    }
+
+    fn make_unmake_nodes(board: &mut Bitboard, depth: uint) -> uint {
+        if depth == 0 || board.is_over() {
+            return 1;
+        }
+        let mut nodes = 1;
+        for &action in ACTIONS.iter() {
+            let undo = board.make(action);
+            nodes += make_unmake_nodes(board, depth - 1);
+            board.unmake(undo);
+        }
+        nodes
+    }
+
+    /// Flood fill over the `GameState` board, the way the bots counted space
+    /// before bitboards.
+    fn game_state_flood_count(game: &GameState, start: Position) -> uint {
+        let mut flooded = Vec::from_elem(game.board_height, Vec::from_elem(game.board_width, false));
+        let mut count = 0u;
+        let mut stack = vec![start];
+        loop {
+            let position = match stack.pop() {
+                Some(position) => position,
+                None => break
+            };
+            for &direction in [North, East, South, West].iter() {
+                match game.step(position, direction) {
+                    Some((r, c)) if game.board[r][c].is_passable() && !flooded[r][c] => {
+                        *flooded.get_mut(r).get_mut(c) = true;
+                        count += 1;
+                        stack.push((r, c));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        count
+    }
+
+    // The search benchmarks count one "byte" per node, so their MB/s read
+    // as millions of nodes per second.
+
+    #[bench]
+    fn search_by_cloning(b: &mut Bencher) {
+        let game = opening();
+        b.bytes = clone_nodes(&game, DEPTH) as u64;
+        b.iter(|| clone_nodes(&game, DEPTH));
+    }
+
+    #[bench]
+    fn search_by_make_unmake(b: &mut Bencher) {
+        let mut board = Bitboard::from_game(&opening());
+        b.bytes = make_unmake_nodes(&mut board, DEPTH) as u64;
+        b.iter(|| make_unmake_nodes(&mut board, DEPTH));
+    }
+
+    #[bench]
+    fn flood_count_from_game_state(b: &mut Bencher) {
+        let game = opening();
+        b.iter(|| game_state_flood_count(&game, game.players[0].position));
+    }
+
+    #[bench]
+    fn flood_count_on_bitboard(b: &mut Bencher) {
+        let board = Bitboard::from_game(&opening());
+        b.iter(|| board.flood_count(board.heads[0]));
+    }
+}
@@ -0,0 +1,119 @@
+//! Time controls and the players' clocks.
+
+use std::cmp::max;
+
+/// How much time a player gets for its moves.
+#[deriving(Clone, Show, PartialEq)]
+pub enum TimeControl {
+    PerMove(i64), // Milliseconds for every move.
+    Fischer(i64, i64) // Milliseconds in the bank at the start, and added after every move.
+}
+
+/// What happens to a player that does not answer in time.
+#[deriving(Clone, Show, PartialEq)]
+pub enum LatePolicy {
+    RepeatLast, // The player's previous action is played again.
+    Forfeit, // The player is out of the game and placed last.
+    CrashLate // The player crashes where it stands.
+}
+
+impl TimeControl {
+    /// The command-line form of the time control.
+    pub fn describe(&self) -> String {
+        match *self {
+            PerMove(ms) => ms.to_string(),
+            Fischer(bank, increment) => format!("{}+{}", bank, increment)
+        }
+    }
+}
+
+/// Parses `<ms>` for a fixed time per move or `<bank ms>+<increment ms>`
+/// for a Fischer clock.
+pub fn parse_time_control(spec: &str) -> Result<TimeControl, String> {
+    let parts: Vec<&str> = spec.split('+').collect();
+    let numbers: Vec<i64> = parts.iter().filter_map(|part| from_str(*part)).collect();
+    if numbers.len() == parts.len() {
+        if numbers.len() == 1 && numbers[0] > 0 {
+            return Ok(PerMove(numbers[0]));
+        }
+        if numbers.len() == 2 && numbers[0] > 0 && numbers[1] >= 0 {
+            return Ok(Fischer(numbers[0], numbers[1]));
+        }
+    }
+    Err(format!("invalid time control {}, expected <ms> or <bank ms>+<increment ms>", spec))
+}
+
+pub fn parse_late_policy(spec: &str) -> Result<LatePolicy, String> {
+    match spec {
+        "repeat" => Ok(RepeatLast),
+        "forfeit" => Ok(Forfeit),
+        "crash" => Ok(CrashLate),
+        _ => Err(format!("unknown late-move policy {}, expected repeat, forfeit or crash", spec))
+    }
+}
+
+/// A player's clock, with statistics about the time it took.
+#[deriving(Clone, Show)]
+pub struct Clock {
+    pub control: TimeControl,
+    pub bank_ms: i64, // Time left under a Fischer clock.
+    pub moves: uint,
+    pub late_moves: uint,
+    pub total_ns: u64, // Time taken over all moves; late moves count their whole allowance.
+    pub longest_ns: u64
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            bank_ms: match control {
+                PerMove(_) => 0,
+                Fischer(bank, _) => bank
+            },
+            control: control,
+            moves: 0,
+            late_moves: 0,
+            total_ns: 0,
+            longest_ns: 0
+        }
+    }
+
+    /// Milliseconds the player may take for its next move.
+    pub fn allowance_ms(&self) -> i64 {
+        match self.control {
+            PerMove(ms) => ms,
+            Fischer(_, _) => self.bank_ms
+        }
+    }
+
+    /// Records a move answered after `elapsed_ns`, or not answered at all,
+    /// and returns whether it was in time.
+    pub fn record(&mut self, elapsed_ns: Option<u64>) -> bool {
+        let allowance_ns = (self.allowance_ms() as u64) * 1000000;
+        let (taken_ns, in_time) = match elapsed_ns {
+            Some(ns) => (ns, ns <= allowance_ns),
+            None => (allowance_ns, false)
+        };
+        self.moves += 1;
+        if !in_time {
+            self.late_moves += 1;
+        }
+        self.total_ns += taken_ns;
+        self.longest_ns = max(self.longest_ns, taken_ns);
+        match self.control {
+            PerMove(_) => {}
+            Fischer(_, increment) => {
+                self.bank_ms = max(self.bank_ms - (taken_ns / 1000000) as i64, 0) + increment;
+            }
+        }
+        in_time
+    }
+
+    pub fn mean_ms(&self) -> f64 {
+        if self.moves == 0 {
+            0.0
+        } else {
+            self.total_ns as f64 / self.moves as f64 / 1e6
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use game::{Action, Rules, Alternating, Simultaneous, Topology, Bounded};
+use getopts::{optopt, optflag, optmulti, getopts, usage, OptGroup};
+use std::from_str::FromStr;
+use tournament::{TournamentSettings, RoundRobin, Swiss};
+use series::{SeriesFormat, BestOf, FirstTo};
+use map;
+use map::Map;
+use keys::{KeyBindings, parse_key_bindings, default_key_bindings};
+use clock::{TimeControl, PerMove, LatePolicy, RepeatLast, parse_time_control, parse_late_policy};
+
+pub static MAX_PLAYERS: uint = 8;
+pub static MIN_BOARD_SIZE: uint = 5;
+pub static DEFAULT_FRAME_DELAY_MS: i64 = 1000;
+pub static DEFAULT_REPLAY_PATH: &'static str = "last-match.replay";
+
+/// How the minimax bots score positions they cannot search to the end.
+#[deriving(Clone, Show, PartialEq)]
+pub enum Heuristic {
+    Space, // Compare the space each player can still reach.
+    Voronoi // Compare the cells each player reaches first.
+}
+
+impl Heuristic {
+    fn name(&self) -> &'static str {
+        match *self {
+            Space => "space",
+            Voronoi => "voronoi"
+        }
+    }
+}
+
+/// How a player's moves are chosen.
+#[deriving(Clone, Show, PartialEq)]
+pub enum BehaviourSpec {
+    Minimax(Heuristic),
+    MinimaxMemory(Heuristic),
+    Mcts,
+    StupidRandom(f64), // Stability: mean number of moves between random turns.
+    StaticAction(Action),
+    Keyboard,
+    External(String, Vec<String>) // Program and its arguments.
+}
+
+static BEHAVIOUR_HELP: &'static str = "Behaviours: minimax[:space|:voronoi], \
+minimax_memory[:space|:voronoi], mcts, stupid_random:<stability>, \
+static:<F|L|R>, keyboard, external:<command line>";
+
+impl BehaviourSpec {
+    /// The command-line form of the behaviour.
+    pub fn describe(&self) -> String {
+        match *self {
+            Minimax(Space) => "minimax".to_string(),
+            Minimax(heuristic) => format!("minimax:{}", heuristic.name()),
+            MinimaxMemory(Space) => "minimax_memory".to_string(),
+            MinimaxMemory(heuristic) => format!("minimax_memory:{}", heuristic.name()),
+            Mcts => "mcts".to_string(),
+            StupidRandom(stability) => format!("stupid_random:{}", stability),
+            StaticAction(action) => format!("static:{}", action.symbol()),
+            Keyboard => "keyboard".to_string(),
+            External(ref program, ref args) => {
+                let mut words = vec![program.clone()];
+                words.push_all(args.as_slice());
+                format!("external:{}", words.connect(" "))
+            }
+        }
+    }
+}
+
+fn parse_heuristic(name: &str) -> Result<Heuristic, String> {
+    match name {
+        "space" => Ok(Space),
+        "voronoi" => Ok(Voronoi),
+        _ => Err(format!("unknown heuristic {}, expected space or voronoi", name))
+    }
+}
+
+pub fn parse_behaviour(spec: &str) -> Result<BehaviourSpec, String> {
+    let (name, argument) = match spec.find(':') {
+        Some(i) => (spec.slice_to(i), Some(spec.slice_from(i + 1))),
+        None => (spec, None)
+    };
+    match (name, argument) {
+        ("minimax", None) => Ok(Minimax(Space)),
+        ("minimax", Some(heuristic)) => parse_heuristic(heuristic).map(|h| Minimax(h)),
+        ("minimax_memory", None) => Ok(MinimaxMemory(Space)),
+        ("minimax_memory", Some(heuristic)) => parse_heuristic(heuristic).map(|h| MinimaxMemory(h)),
+        ("mcts", None) => Ok(Mcts),
+        ("keyboard", None) => Ok(Keyboard),
+        ("stupid_random", Some(stability)) => match from_str::<f64>(stability) {
+            Some(stability) if stability > 0.0 => Ok(StupidRandom(stability)),
+            _ => Err(format!("invalid stability in {}, expected a positive number", spec))
+        },
+        ("static", Some(action)) => match (action.char_len(), action.chars().next().and_then(|c| Action::from_symbol(c))) {
+            (1, Some(action)) => Ok(StaticAction(action)),
+            _ => Err(format!("invalid action in {}, expected F, L or R", spec))
+        },
+        ("external", Some(command)) if !command.trim().is_empty() => {
+            let words: Vec<String> = command.words().map(|word| word.to_string()).collect();
+            Ok(External(words[0].clone(), words.slice_from(1).to_vec()))
+        },
+        _ => Err(format!("unknown behaviour {}. {}", spec, BEHAVIOUR_HELP))
+    }
+}
+
+#[deriving(Clone, Show)]
+pub struct Config {
+    pub board_width: uint,
+    pub board_height: uint,
+    pub map: Option<Map>, // Sets the board size, obstacles and start positions.
+    pub random_starts: bool, // Generate fair starts from the seed instead of using the fixed ones.
+    pub behaviours: Vec<BehaviourSpec>, // One per player.
+    pub key_bindings: Vec<KeyBindings>, // One per keyboard player, in seat order.
+    pub rules: Rules,
+    pub topology: Topology,
+    pub turn_limit: Option<uint>,
+    pub frame_delay_ms: i64,
+    pub time_controls: Vec<TimeControl>, // One per behaviour.
+    pub late_policy: LatePolicy,
+    pub seed: Option<u64>,
+    pub replay_path: String,
+    pub headless: bool,
+    pub ponder: bool // Let bots that can think during other players' turns do so.
+}
+
+/// What the program was asked to do.
+pub enum Invocation {
+    PlayMatch(Config),
+    WatchReplay(String, i64), // Replay file and initial frame delay.
+    RunTournament(Config, TournamentSettings),
+    PlaySeries(Config, SeriesFormat),
+    ShowUsage(String)
+}
+
+fn options() -> Vec<OptGroup> {
+    vec![
+        optopt("W", "width", "board width (default 40)", "COLUMNS"),
+        optopt("H", "height", "board height (default 20)", "ROWS"),
+        optopt("m", "map", format!("play on a map file or a bundled map: {}",
+                                   map::bundled_names().connect(", ")).as_slice(), "MAP"),
+        optflag("", "random-starts", "start from random symmetric positions chosen by the seed, \
+                                      in tournaments a new one for every opening"),
+        optopt("n", "players", "number of players, 2 to 8 (default: one per --bot, at least 2)", "COUNT"),
+        optmulti("b", "bot", "behaviour of the next player (default minimax)", "BEHAVIOUR"),
+        optflag("k", "keyboard", "let the first player be controlled from the keyboard"),
+        optmulti("", "keys", "keys of the next keyboard player: arrows, vi, four keys for up, left, down and right \
+                              such as wasd, or several joined by + (default arrows+vi, then wasd)", "KEYS"),
+        optflag("s", "simultaneous", "all players move at the same time"),
+        optopt("", "topology", "what the board edges do: bounded, wrap-horizontal, wrap-vertical or torus \
+                                (default bounded)", "TOPOLOGY"),
+        optopt("t", "turn-limit", "end the game in a draw after this many turns", "TURNS"),
+        optopt("d", "delay", "milliseconds per turn (default 1000)", "MS"),
+        optmulti("", "time", "time control of the next player, <ms> per move or <bank ms>+<increment ms> \
+                              (default: the delay per move; the last one given applies to the remaining players)", "CONTROL"),
+        optopt("", "late", "what happens to players that answer too late: repeat their last action, forfeit or crash \
+                            (default repeat)", "POLICY"),
+        optopt("", "seed", "seed for random choices", "SEED"),
+        optopt("o", "record", format!("where to save the replay (default {})", DEFAULT_REPLAY_PATH).as_slice(), "FILE"),
+        optopt("", "tournament", "play a tournament between the --bot behaviours, round-robin or swiss:<rounds>", "FORMAT"),
+        optopt("", "games", "openings per tournament pairing, each played from both seats (default 2)", "COUNT"),
+        optopt("", "series", "play several games, rotating the seats: best-of:<games> or first-to:<wins>", "FORMAT"),
+        optflag("", "ponder", "let bots that can think during other players' turns do so (mcts)"),
+        optflag("", "headless", "play without the terminal interface as fast as the bots answer and print the result"),
+        optopt("r", "replay", "watch a recorded match instead of playing", "FILE"),
+        optflag("h", "help", "show this help")
+    ]
+}
+
+fn parse_number<T: FromStr>(value: Option<String>, flag: &str, default: T) -> Result<T, String> {
+    match value {
+        Some(value) => match from_str(value.as_slice()) {
+            Some(x) => Ok(x),
+            None => Err(format!("--{} expects a number, got {}", flag, value))
+        },
+        None => Ok(default)
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Invocation, String> {
+    let program = args[0].clone();
+    let options = options();
+    let matches = match getopts(args.tail(), options.as_slice()) {
+        Ok(matches) => matches,
+        Err(f) => return Err(f.to_string())
+    };
+    if matches.opt_present("h") {
+        let brief = format!("Usage: {} [options]\n\n{}.", program, BEHAVIOUR_HELP);
+        return Ok(ShowUsage(usage(brief.as_slice(), options.as_slice())));
+    }
+    if !matches.free.is_empty() {
+        return Err(format!("unexpected argument {}", matches.free[0]));
+    }
+
+    let frame_delay_ms = try!(parse_number(matches.opt_str("d"), "delay", DEFAULT_FRAME_DELAY_MS));
+    if frame_delay_ms <= 0 {
+        return Err("--delay must be positive".to_string());
+    }
+    match matches.opt_str("r") {
+        Some(path) => return Ok(WatchReplay(path, frame_delay_ms)),
+        None => {}
+    }
+
+    let map = match matches.opt_str("m") {
+        Some(name) => Some(try!(Map::find(name.as_slice()))),
+        None => None
+    };
+    let (board_width, board_height) = match map {
+        Some(ref map) => {
+            if matches.opt_present("W") || matches.opt_present("H") {
+                return Err("the board size comes from the map, --width and --height cannot be used with --map".to_string());
+            }
+            (map.width, map.height)
+        }
+        None => {
+            let board_width = try!(parse_number(matches.opt_str("W"), "width", 40u));
+            let board_height = try!(parse_number(matches.opt_str("H"), "height", 20u));
+            if board_width < MIN_BOARD_SIZE || board_height < MIN_BOARD_SIZE {
+                return Err(format!("the board must be at least {0}x{0}", MIN_BOARD_SIZE));
+            }
+            (board_width, board_height)
+        }
+    };
+
+    let topology = match matches.opt_str("topology") {
+        Some(name) => match Topology::from_name(name.as_slice()) {
+            Some(topology) => topology,
+            None => return Err(format!("unknown topology {}, expected bounded, wrap-horizontal, wrap-vertical or torus", name))
+        },
+        None => Bounded
+    };
+
+    let turn_limit = match matches.opt_str("t") {
+        Some(value) => match from_str::<uint>(value.as_slice()) {
+            Some(limit) if limit > 0 => Some(limit),
+            _ => return Err("--turn-limit expects a positive number".to_string())
+        },
+        None => None
+    };
+    let seed = match matches.opt_str("seed") {
+        Some(value) => Some(try!(parse_number(Some(value), "seed", 0u64))),
+        None => None
+    };
+
+    let mut behaviours = vec![];
+    if matches.opt_present("k") {
+        behaviours.push(Keyboard);
+    }
+    for spec in matches.opt_strs("b").iter() {
+        behaviours.push(try!(parse_behaviour(spec.as_slice())));
+    }
+
+    let tournament = match matches.opt_str("tournament") {
+        Some(format) => {
+            let format = match format.as_slice() {
+                "round-robin" => RoundRobin,
+                f if f.starts_with("swiss:") => match from_str::<uint>(f.slice_from(6)) {
+                    Some(rounds) if rounds > 0 => Swiss(rounds),
+                    _ => return Err("--tournament swiss:<rounds> expects a positive number of rounds".to_string())
+                },
+                _ => return Err(format!("unknown tournament format {}, expected round-robin or swiss:<rounds>", format))
+            };
+            let games_per_pairing = try!(parse_number(matches.opt_str("games"), "games", 2u));
+            if games_per_pairing == 0 {
+                return Err("--games must be positive".to_string());
+            }
+            if behaviours.len() < 2 {
+                return Err("a tournament needs at least two --bot behaviours".to_string());
+            }
+            if matches.opt_present("n") {
+                return Err("tournament games are always played by two players".to_string());
+            }
+            if behaviours.contains(&Keyboard) {
+                return Err("keyboard players cannot enter tournaments".to_string());
+            }
+            Some(TournamentSettings {
+                format: format,
+                games_per_pairing: games_per_pairing
+            })
+        }
+        None => None
+    };
+
+    let series = match matches.opt_str("series") {
+        Some(format) => {
+            let (kind, count) = match format.as_slice().find(':') {
+                Some(i) => (format.as_slice().slice_to(i), from_str::<uint>(format.as_slice().slice_from(i + 1))),
+                None => (format.as_slice(), None)
+            };
+            let format = match (kind, count) {
+                ("best-of", Some(games)) if games > 0 => BestOf(games),
+                ("first-to", Some(wins)) if wins > 0 => FirstTo(wins),
+                _ => return Err(format!("unknown series format {}, expected best-of:<games> or first-to:<wins>", format))
+            };
+            if tournament.is_some() {
+                return Err("--series cannot be combined with --tournament".to_string());
+            }
+            Some(format)
+        }
+        None => None
+    };
+
+    if tournament.is_none() {
+        let player_count = try!(parse_number(matches.opt_str("n"), "players", if behaviours.len() > 2 { behaviours.len() } else { 2 }));
+        if player_count < 2 || player_count > MAX_PLAYERS {
+            return Err(format!("--players must be between 2 and {}", MAX_PLAYERS));
+        }
+        if behaviours.len() > player_count {
+            return Err(format!("{} behaviours given for {} players", behaviours.len(), player_count));
+        }
+        match map {
+            Some(ref map) if player_count > map.starts.len() && !matches.opt_present("random-starts") => {
+                return Err(format!("map {} has start cells for {} players", map.name, map.starts.len()));
+            }
+            _ => {}
+        }
+        while behaviours.len() < player_count {
+            behaviours.push(Minimax(Space));
+        }
+    }
+    let keyboard_players = behaviours.iter().filter(|spec| **spec == Keyboard).count();
+    let mut key_bindings = vec![];
+    for spec in matches.opt_strs("keys").iter() {
+        key_bindings.push(try!(parse_key_bindings(spec.as_slice())));
+    }
+    if key_bindings.len() > keyboard_players {
+        return Err(format!("{} key bindings given for {} keyboard players", key_bindings.len(), keyboard_players));
+    }
+    while key_bindings.len() < keyboard_players {
+        match default_key_bindings(key_bindings.len()) {
+            Some(bindings) => key_bindings.push(bindings),
+            None => return Err(format!("keyboard player {} needs --keys", key_bindings.len() + 1))
+        }
+    }
+    for (i, bindings) in key_bindings.iter().enumerate() {
+        match key_bindings.slice_to(i).iter().find(|other| bindings.overlaps(*other)) {
+            Some(other) => return Err(format!("key bindings {} and {} share keys", other.spec, bindings.spec)),
+            None => {}
+        }
+    }
+
+    let mut time_controls = vec![];
+    for spec in matches.opt_strs("time").iter() {
+        time_controls.push(try!(parse_time_control(spec.as_slice())));
+    }
+    if time_controls.len() > behaviours.len() {
+        return Err(format!("{} time controls given for {} players", time_controls.len(), behaviours.len()));
+    }
+    let last_control = time_controls.last().map_or(PerMove(frame_delay_ms), |control| control.clone());
+    while time_controls.len() < behaviours.len() {
+        time_controls.push(last_control.clone());
+    }
+    let late_policy = match matches.opt_str("late") {
+        Some(policy) => try!(parse_late_policy(policy.as_slice())),
+        None => RepeatLast
+    };
+
+    let headless = matches.opt_present("headless") || tournament.is_some();
+    if headless && behaviours.contains(&Keyboard) {
+        return Err("keyboard players need the terminal interface, not --headless".to_string());
+    }
+
+    let config = Config {
+        board_width: board_width,
+        board_height: board_height,
+        map: map,
+        random_starts: matches.opt_present("random-starts"),
+        behaviours: behaviours,
+        key_bindings: key_bindings,
+        rules: if matches.opt_present("s") { Simultaneous } else { Alternating },
+        topology: topology,
+        turn_limit: turn_limit,
+        frame_delay_ms: frame_delay_ms,
+        time_controls: time_controls,
+        late_policy: late_policy,
+        seed: seed,
+        replay_path: matches.opt_str("o").unwrap_or(DEFAULT_REPLAY_PATH.to_string()),
+        headless: headless,
+        ponder: matches.opt_present("ponder")
+    };
+    Ok(match (tournament, series) {
+        (Some(settings), _) => RunTournament(config, settings),
+        (None, Some(format)) => PlaySeries(config, format),
+        (None, None) => PlayMatch(config)
+    })
+}
@@ -0,0 +1,306 @@
+use game::{Action, ActionMessage, MoveForward, Behaviour, GameState, PlayerIndex};
+use game::{Alternating, Simultaneous, PlayerTurn, Won, Draw};
+use clock::{Clock, TimeControl, LatePolicy, RepeatLast, Forfeit, CrashLate};
+use replay::{Replay, Failure};
+use std::io::{Timer, IoResult};
+use std::time::Duration;
+use std::comm::{Select, Handle};
+use time::precise_time_ns;
+
+/// Milliseconds a behaviour may go without sending anything, beyond the time
+/// its clock allows for the current move, before it is considered hung. It
+/// is wall-clock time so that a slow bot with an empty bank is merely late.
+/// A hung task cannot be stopped; it is left running and ignored.
+static MAX_SILENCE_MS: u64 = 10000;
+
+pub struct MatchSettings {
+    pub time_controls: Vec<TimeControl>, // One per player.
+    pub late_policy: LatePolicy,
+    pub frame_delay_ms: Option<i64> // Shortest turn, so that people can follow the game.
+}
+
+pub struct MatchResult {
+    pub game: GameState, // Final state.
+    pub replay: Replay,
+    pub aborted: bool,
+    pub clocks: Vec<Clock>, // Each player's clock at the end, with its timing statistics.
+    pub forfeited: Vec<PlayerIndex>, // Players out for running out of time.
+    pub failures: Vec<Failure> // Behaviours that crashed or hung; their players are crashed.
+}
+
+/// Indices separated by spaces, as summaries write them.
+pub fn index_list(indices: &[uint]) -> String {
+    indices.iter().map(|i| i.to_string()).collect::<Vec<String>>().connect(" ")
+}
+
+impl MatchResult {
+    /// Players sharing first place, leaving out players that forfeited.
+    pub fn winners(&self) -> Vec<PlayerIndex> {
+        self.game.winners().move_iter().filter(|p| !self.forfeited.contains(p)).collect()
+    }
+
+    /// Finishing order like `GameState::ranking`, with players that
+    /// forfeited sharing the last place.
+    pub fn ranking(&self) -> Vec<Vec<PlayerIndex>> {
+        let mut places: Vec<Vec<PlayerIndex>> = self.game.ranking().move_iter().map(|place| {
+            place.move_iter().filter(|p| !self.forfeited.contains(p)).collect::<Vec<PlayerIndex>>()
+        }).filter(|place| !place.is_empty()).collect();
+        if !self.forfeited.is_empty() {
+            places.push(self.forfeited.clone());
+        }
+        places
+    }
+
+    /// Writes a line-based summary meant for scripts: the outcome, the
+    /// number of turns, the winners, every place of the finishing order,
+    /// the players that forfeited, each player's timing (moves, late moves,
+    /// and mean and longest milliseconds per move) and the behaviours that
+    /// failed, with the turn and the reason.
+    pub fn write_summary(&self, writer: &mut Writer) -> IoResult<()> {
+        let outcome = match self.game.status {
+            _ if self.aborted => "aborted",
+            Won(_) => "won",
+            Draw(_) => "draw",
+            _ => "unfinished"
+        };
+        try!(writer.write_str(format!("result {}\n", outcome).as_slice()));
+        try!(writer.write_str(format!("turns {}\n", self.game.turn).as_slice()));
+        try!(writer.write_str(format!("seed {}\n", self.replay.seed).as_slice()));
+        try!(writer.write_str(format!("winners {}\n", index_list(self.winners().as_slice())).as_slice()));
+        for (place, players) in self.ranking().iter().enumerate() {
+            try!(writer.write_str(format!("place {} {}\n", place + 1, index_list(players.as_slice())).as_slice()));
+        }
+        try!(writer.write_str(format!("forfeited {}\n", index_list(self.forfeited.as_slice())).as_slice()));
+        for (p, clock) in self.clocks.iter().enumerate() {
+            try!(writer.write_str(format!("time {} {} {} {:.1f} {:.1f}\n", p, clock.moves, clock.late_moves,
+                                          clock.mean_ms(), clock.longest_ns as f64 / 1e6).as_slice()));
+        }
+        for failure in self.failures.iter() {
+            try!(writer.write_str(format!("failed {} {} {}\n", failure.player, failure.turn,
+                                          failure.reason).as_slice()));
+        }
+        Ok(())
+    }
+}
+
+enum Answer {
+    NoAnswer, // Not acting, or not answering in time.
+    Answered(Action),
+    Failed(String) // The behaviour crashed or hung.
+}
+
+/// Sends `game` to the behaviours of the acting players, giving each the
+/// time its clock allows, and collects their answers, indexed by player.
+/// `silent_since` holds for each player the time at which its behaviour was
+/// sent the first of the states it has not answered anything to since.
+fn collect_actions(game: &GameState, behaviours: &[Behaviour], clocks: &mut [Clock],
+                   silent_since: &mut [Option<u64>], settings: &MatchSettings) -> Vec<Answer> {
+    let acting = game.acting_players();
+    for &p in acting.iter() {
+        behaviours[p].send_state(p, game, clocks[p].allowance_ms());
+    }
+    // Pondering behaviours follow the game between their own turns too.
+    for (p, behaviour) in behaviours.iter().enumerate() {
+        if behaviour.ponders && game.players[p].is_alive && !acting.contains(&p) {
+            behaviour.send_state(p, game, 0);
+        }
+    }
+    let start = precise_time_ns();
+    let longest_allowance = acting.iter().map(|&p| clocks[p].allowance_ms()).max().unwrap_or(0);
+    let mut timer = Timer::new().unwrap();
+    let timeout = timer.oneshot(Duration::milliseconds(longest_allowance));
+    let mut answers: Vec<Option<(Action, u64)>> = Vec::from_elem(game.players.len(), None);
+    let mut failures: Vec<Option<String>> = Vec::from_elem(game.players.len(), None);
+    let mut heard = Vec::from_elem(game.players.len(), false);
+    let mut missing = acting.len();
+
+    let select = Select::new();
+    let mut timeout_handle  = select.handle(&timeout);
+    let mut behaviour_handles: Vec<Handle<ActionMessage>> = acting.iter().map(|&p| {
+        select.handle(&behaviours[p].receiver)
+    }).collect();
+    unsafe {
+        timeout_handle.add();
+        for handle in behaviour_handles.mut_iter() {
+            handle.add();
+        }
+    }
+    while missing > 0 {
+        let id = select.wait();
+        if id == timeout_handle.id() {
+            break;
+        }
+        match behaviour_handles.iter().position(|handle| handle.id() == id) {
+            Some(i) => {
+                let p = acting[i];
+                match behaviours[p].receiver.recv_opt() {
+                    Ok((id, turn, a)) => {
+                        *heard.get_mut(p) = true;
+                        if id != behaviours[p].game_id() {
+                            warn!("Turn {}: received action from a previous game from player {}.", game.turn, p);
+                        } else if turn == game.turn && answers[p].is_none() {
+                            *answers.get_mut(p) = Some((a, precise_time_ns() - start));
+                            missing -= 1;
+                        } else {
+                            warn!("Turn {}: received action for wrong turn {} from player {}.", game.turn, turn, p);
+                        }
+                    }
+                    Err(()) => {
+                        // The behaviour's task has ended; an answer it sent
+                        // before still counts for this turn.
+                        unsafe { behaviour_handles.get_mut(i).remove(); }
+                        if answers[p].is_none() {
+                            *failures.get_mut(p) = Some(behaviours[p].failure_reason());
+                            missing -= 1;
+                        }
+                    }
+                }
+            }
+            None => {}
+        };
+    };
+    // Give people watching the rest of the frame.
+    match settings.frame_delay_ms {
+        Some(delay) => {
+            let elapsed_ms = ((precise_time_ns() - start) / 1000000) as i64;
+            if elapsed_ms < delay {
+                timer.sleep(Duration::milliseconds(delay - elapsed_ms));
+            }
+        }
+        None => {}
+    }
+
+    range(0, game.players.len()).map(|p| {
+        if !acting.contains(&p) {
+            return NoAnswer;
+        }
+        match failures.get_mut(p).take() {
+            Some(reason) => return Failed(reason),
+            None => {}
+        }
+        let allowance_ms = clocks[p].allowance_ms() as u64;
+        let in_time = clocks[p].record(answers[p].map(|(_, elapsed)| elapsed));
+        if heard[p] {
+            silent_since[p] = None;
+        } else if silent_since[p].is_none() {
+            silent_since[p] = Some(start);
+        }
+        match silent_since[p] {
+            Some(since) => {
+                let silent_ms = (precise_time_ns() - since) / 1000000;
+                if silent_ms > allowance_ms + MAX_SILENCE_MS {
+                    return Failed(format!("sent nothing for {} ms", silent_ms));
+                }
+            }
+            None => {}
+        }
+        match answers[p] {
+            Some((action, _)) if in_time => Answered(action),
+            _ => {
+                warn!("Turn {}, player {}: action was not set fast enough.", game.turn, p);
+                NoAnswer
+            }
+        }
+    }).collect()
+}
+
+/// Plays `game` to the end with one behaviour per player.
+///
+/// `observe` is called before every turn and once the game is over, with a
+/// flag telling whether every living player has moved since the previous
+/// round; returning `false` aborts the match.
+pub fn play_match(mut game: GameState, behaviours: &[Behaviour], settings: &MatchSettings, seed: u64,
+                  observe: |&GameState, bool| -> bool) -> MatchResult {
+    assert!(behaviours.len() == game.players.len());
+    assert!(settings.time_controls.len() == game.players.len());
+    let mut replay = Replay::new(&game, seed);
+    let mut clocks: Vec<Clock> = settings.time_controls.iter().map(|control| Clock::new(control.clone())).collect();
+    let mut last_actions = Vec::from_elem(game.players.len(), MoveForward);
+    let mut forfeited = vec![];
+    let mut failures = vec![];
+    let mut silent_since = Vec::from_elem(game.players.len(), None);
+    let mut round_over = true;
+    let mut aborted = false;
+    for behaviour in behaviours.iter() {
+        behaviour.start_game();
+    }
+
+    loop {
+        if !observe(&game, round_over) {
+            aborted = true;
+            break;
+        }
+        if game.is_over() {
+            break;
+        }
+
+        let acting = game.acting_players();
+        debug!("Turn {}, players {}", game.turn, acting);
+        let answers = collect_actions(&game, behaviours, clocks.as_mut_slice(), silent_since.as_mut_slice(), settings);
+        let mut late = vec![];
+        let mut failed = vec![];
+        for &p in acting.iter() {
+            match answers[p] {
+                Answered(action) => *last_actions.get_mut(p) = action,
+                NoAnswer => late.push(p),
+                Failed(ref reason) => {
+                    warn!("Turn {}, player {}: behaviour failed: {}", game.turn, p, reason);
+                    let failure = Failure {
+                        player: p,
+                        turn: game.turn,
+                        reason: reason.clone()
+                    };
+                    replay.record_failure(failure.clone());
+                    failures.push(failure);
+                    failed.push(p);
+                }
+            }
+        }
+
+        // Players whose behaviour failed crash. Late players repeat their
+        // last action unless they are out.
+        let mut out = failed;
+        match settings.late_policy {
+            RepeatLast => {}
+            Forfeit => {
+                forfeited.push_all(late.as_slice());
+                out.push_all(late.as_slice());
+            }
+            CrashLate => out.push_all(late.as_slice())
+        }
+        let eliminated = !out.is_empty();
+        if eliminated {
+            replay.record_elimination(out.as_slice());
+            game.eliminate(out.as_slice());
+        }
+        // An eliminated player to move has had its turn.
+        if !game.is_over() && !(eliminated && game.rules == Alternating) {
+            replay.record_turn(&game, last_actions.as_slice());
+            match game.rules {
+                Alternating => game.do_turn(last_actions[acting[0]]),
+                Simultaneous => game.do_tick(last_actions.as_slice())
+            }
+        }
+
+        round_over = match game.status {
+            PlayerTurn(p) => p <= acting[0],
+            _ => true
+        };
+    }
+
+    // Let the behaviours know the game has ended so that they can stop.
+    if game.is_over() {
+        for (p, behaviour) in behaviours.iter().enumerate() {
+            behaviour.send_state(p, &game, 0);
+        }
+    }
+
+    MatchResult {
+        game: game,
+        replay: replay,
+        aborted: aborted,
+        clocks: clocks,
+        forfeited: forfeited,
+        failures: failures
+    }
+}
@@ -0,0 +1,321 @@
+//! Play once the players can no longer reach each other.
+//!
+//! A player walled off from everyone else only has to survive as long as
+//! possible, which means finding the longest path through its own region.
+//! Small regions are searched exhaustively. Larger ones are split into
+//! chambers at their articulation points, the cells whose filling cuts the
+//! region apart: a path can fill the chamber it is in and then only go on
+//! through one of its exits. Each chamber is bounded by checkerboard parity,
+//! as every step alternates between the two colours of the board, unless
+//! the board wraps around an odd number of cells, which breaks the pattern.
+
+use game::{Action, MoveForward, GameState, PlayerIndex, Direction};
+use game::{North, East, South, West};
+use std::cmp::min;
+use time::precise_time_ns;
+
+/// Regions with at most this many cells are searched exhaustively.
+static EXACT_REGION_SIZE: uint = 40;
+
+/// Paths explored by the exhaustive search before it falls back to the
+/// estimate.
+static EXACT_NODE_LIMIT: uint = 2000000;
+
+/// Chambers looked at by one estimate, and chambers passed through on the
+/// way to any one of them, before the rest of a region is judged by parity
+/// alone.
+static CHAMBER_LIMIT: uint = 20000;
+static CHAMBER_DEPTH_LIMIT: uint = 200;
+
+static DIRECTIONS: [Direction, ..4] = [North, East, South, West];
+
+/// The board's free cells, numbered row by row.
+struct Grid {
+    width: uint,
+    free: Vec<bool>,
+    adjacent: Vec<Vec<uint>>, // Neighbours of each cell, free or not.
+    two_coloured: bool // Whether every step changes the checkerboard colour.
+}
+
+impl Grid {
+    fn new(game: &GameState) -> Grid {
+        let (width, height) = (game.board_width, game.board_height);
+        let mut free = Vec::with_capacity(width * height);
+        let mut adjacent = Vec::with_capacity(width * height);
+        for r in range(0, height) {
+            for c in range(0, width) {
+                free.push(game.board[r][c].is_passable());
+                adjacent.push(DIRECTIONS.iter().filter_map(|&direction| {
+                    game.step((r, c), direction).map(|(nr, nc)| nr * width + nc)
+                }).collect());
+            }
+        }
+        Grid {
+            width: width,
+            free: free,
+            adjacent: adjacent,
+            two_coloured: !(game.topology.wraps_horizontally() && width % 2 == 1) &&
+                !(game.topology.wraps_vertically() && height % 2 == 1)
+        }
+    }
+
+    fn colour(&self, cell: uint) -> uint {
+        (cell / self.width + cell % self.width) % 2
+    }
+
+    /// Free cells reachable from `start`, not counting `start` itself.
+    fn region(&self, start: uint) -> Vec<uint> {
+        let mut seen = Vec::from_elem(self.free.len(), false);
+        *seen.get_mut(start) = true;
+        let mut cells = vec![];
+        let mut stack = vec![start];
+        loop {
+            let cell = match stack.pop() {
+                Some(cell) => cell,
+                None => break
+            };
+            for &next in self.adjacent[cell].iter() {
+                if self.free[next] && !seen[next] {
+                    *seen.get_mut(next) = true;
+                    cells.push(next);
+                    stack.push(next);
+                }
+            }
+        }
+        cells
+    }
+}
+
+/// Whether no other living player can reach any cell `player` can reach.
+pub fn is_separated(game: &GameState, player: PlayerIndex) -> bool {
+    let grid = Grid::new(game);
+    let (r, c) = game.players[player].position;
+    let start = r * grid.width + c;
+    let mut reachable = Vec::from_elem(grid.free.len(), false);
+    *reachable.get_mut(start) = true;
+    for &cell in grid.region(start).iter() {
+        *reachable.get_mut(cell) = true;
+    }
+    game.players.iter().enumerate().all(|(p, other)| {
+        let (r, c) = other.position;
+        p == player || !other.is_alive || !grid.adjacent[r * grid.width + c].iter().any(|&n| reachable[n])
+    })
+}
+
+/// Length of the longest path from `cell` through free cells, or `None`
+/// if the search ran out of nodes or time.
+fn longest_path(grid: &mut Grid, cell: uint, nodes: &mut uint, deadline: u64) -> Option<uint> {
+    *nodes += 1;
+    if *nodes > EXACT_NODE_LIMIT || (*nodes % 1024 == 0 && precise_time_ns() > deadline) {
+        return None;
+    }
+    let mut best = 0;
+    for i in range(0, grid.adjacent[cell].len()) {
+        let next = grid.adjacent[cell][i];
+        if !grid.free[next] {
+            continue;
+        }
+        *grid.free.get_mut(next) = false;
+        let length = longest_path(grid, next, nodes, deadline);
+        *grid.free.get_mut(next) = true;
+        match length {
+            Some(length) => if length + 1 > best { best = length + 1; },
+            None => return None
+        }
+    }
+    Some(best)
+}
+
+/// Articulation points of the region around `root`, found with Tarjan's
+/// depth-first search. The search keeps its own stack, as regions can be
+/// far larger than the task's.
+fn articulation_points(grid: &Grid, root: uint) -> Vec<bool> {
+    let mut order = Vec::from_elem(grid.free.len(), 0u); // Visit number of each cell, 0 if not visited.
+    let mut low = Vec::from_elem(grid.free.len(), 0u); // Lowest visit number reachable through one back edge.
+    let mut points = Vec::from_elem(grid.free.len(), false);
+    let mut visits = 1;
+    *order.get_mut(root) = visits;
+    *low.get_mut(root) = visits;
+    let mut root_children = 0u;
+    // Cells being visited, with their parent and the next neighbour to try.
+    let mut stack: Vec<(uint, Option<uint>, uint)> = vec![(root, None, 0)];
+    loop {
+        let (cell, parent, i) = match stack.last() {
+            Some(&frame) => frame,
+            None => break
+        };
+        if i < grid.adjacent[cell].len() {
+            let top = stack.len() - 1;
+            *stack.get_mut(top) = (cell, parent, i + 1);
+            let next = grid.adjacent[cell][i];
+            if !grid.free[next] || parent == Some(next) {
+                continue;
+            }
+            if order[next] == 0 {
+                if cell == root {
+                    root_children += 1;
+                }
+                visits += 1;
+                *order.get_mut(next) = visits;
+                *low.get_mut(next) = visits;
+                stack.push((next, Some(cell), 0));
+            } else {
+                *low.get_mut(cell) = min(low[cell], order[next]);
+            }
+        } else {
+            stack.pop();
+            match parent {
+                Some(parent) => {
+                    *low.get_mut(parent) = min(low[parent], low[cell]);
+                    if parent != root && low[cell] >= order[parent] {
+                        *points.get_mut(parent) = true;
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+    if root_children > 1 {
+        *points.get_mut(root) = true;
+    }
+    points
+}
+
+/// Longest path through `cells` starting next to `entry`, judging only by
+/// their colours.
+fn parity_bound(grid: &Grid, entry: uint, cells: &[uint]) -> uint {
+    if !grid.two_coloured {
+        return cells.len();
+    }
+    let same = cells.iter().filter(|&&cell| grid.colour(cell) == grid.colour(entry)).count();
+    let other = cells.len() - same;
+    if other > same { 2 * same + 1 } else { 2 * other }
+}
+
+/// Limits on the chamber estimate, which follows every sequence of exits.
+struct ChamberBudget {
+    chambers: uint, // Chambers still to be looked at.
+    deadline: u64
+}
+
+/// Optimistic estimate of the longest path from `entry`: the parity bound
+/// of the chamber around it plus the best estimate beyond one of its exits.
+/// Once the budget runs out, everything still reachable counts as one
+/// chamber. `visited` is left as it was found.
+fn chamber_estimate(grid: &Grid, points: &[bool], entry: uint, visited: &mut Vec<bool>,
+                    depth: uint, budget: &mut ChamberBudget) -> uint {
+    let exhausted = budget.chambers == 0 || depth >= CHAMBER_DEPTH_LIMIT || precise_time_ns() > budget.deadline;
+    if !exhausted {
+        budget.chambers -= 1;
+    }
+    let mut marked = vec![];
+    if !visited[entry] {
+        *visited.get_mut(entry) = true;
+        marked.push(entry);
+    }
+    let mut chamber = vec![];
+    let mut exits = vec![];
+    let mut stack = vec![entry];
+    loop {
+        let cell = match stack.pop() {
+            Some(cell) => cell,
+            None => break
+        };
+        for &next in grid.adjacent[cell].iter() {
+            if !grid.free[next] || visited[next] {
+                continue;
+            }
+            *visited.get_mut(next) = true;
+            marked.push(next);
+            if points[next] && !exhausted {
+                exits.push(next);
+            } else {
+                chamber.push(next);
+                stack.push(next);
+            }
+        }
+    }
+
+    let fill = parity_bound(grid, entry, chamber.as_slice());
+    let beyond = exits.iter().map(|&exit| {
+        1 + chamber_estimate(grid, points, exit, visited, depth + 1, budget)
+    }).max().unwrap_or(0);
+    for &cell in marked.iter() {
+        *visited.get_mut(cell) = false;
+    }
+    fill + beyond
+}
+
+/// Number of moves a player at `start` can still make on `grid`.
+fn fillable_from(grid: &mut Grid, start: uint, deadline: u64) -> uint {
+    if grid.region(start).len() <= EXACT_REGION_SIZE {
+        let mut nodes = 0;
+        match longest_path(grid, start, &mut nodes, deadline) {
+            Some(length) => return length,
+            None => debug!("Exact fill search gave up after {} nodes", nodes)
+        }
+    }
+    let points = articulation_points(grid, start);
+    let mut visited = Vec::from_elem(grid.free.len(), false);
+    let mut budget = ChamberBudget {
+        chambers: CHAMBER_LIMIT,
+        deadline: deadline
+    };
+    chamber_estimate(grid, points.as_slice(), start, &mut visited, 0, &mut budget)
+}
+
+/// Number of moves `player` can still make if nobody else gets in the way,
+/// exact for small regions and estimated for large ones.
+pub fn fillable_space(game: &GameState, player: PlayerIndex, deadline: u64) -> uint {
+    let mut grid = Grid::new(game);
+    let (r, c) = game.players[player].position;
+    let start = r * grid.width + c;
+    fillable_from(&mut grid, start, deadline)
+}
+
+/// The move that lets `player` keep moving the longest. Among equally good
+/// moves, the one leaving the fewest free neighbours is preferred so that
+/// the player hugs walls instead of cutting its region in two.
+pub fn fill_action(game: &GameState, player: PlayerIndex, deadline: u64) -> Action {
+    let mut grid = Grid::new(game);
+    let (r, c) = game.players[player].position;
+    let direction = game.players[player].direction;
+    let start = r * grid.width + c;
+
+    let mut best = None;
+    for target in DIRECTIONS.iter() {
+        let action = match direction.action_for(*target) {
+            Some(action) => action,
+            None => continue
+        };
+        let (nr, nc) = match game.step((r, c), *target) {
+            Some(position) if game.can_move_to(position) => position,
+            _ => continue
+        };
+        let next = nr * grid.width + nc;
+        *grid.free.get_mut(next) = false;
+        let length = 1 + fillable_from(&mut grid, next, deadline);
+        let exits = grid.adjacent[next].iter().filter(|&&n| grid.free[n]).count();
+        *grid.free.get_mut(next) = true;
+        debug!("Filling with {}: {} moves, {} exits", action, length, exits);
+        best = match best {
+            Some((_, best_length, best_exits)) if best_length > length ||
+                (best_length == length && best_exits <= exits) => best,
+            _ => Some((action, length, exits))
+        };
+    }
+    match best {
+        Some((action, _, _)) => action,
+        None => MoveForward
+    }
+}
+
+/// The move `player` should make once walled off from everyone else, or
+/// `None` while it can still meet another player and should search.
+pub fn separated_action(game: &GameState, player: PlayerIndex, deadline: u64) -> Option<Action> {
+    if !is_separated(game, player) {
+        return None;
+    }
+    debug!("Separated from the other players, filling our region");
+    Some(fill_action(game, player, deadline))
+}
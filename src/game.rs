@@ -1,5 +1,12 @@
 use std::vec::Vec;
 use std::string::String;
+use std::f64;
+use zobrist;
+use time::precise_time_ns;
+use std::comm;
+use std::cell::Cell;
+use std::task;
+use std::any::{Any, AnyRefExt};
 
 pub type Position = (uint, uint);
 
@@ -30,17 +37,6 @@ impl Direction {
         }
     }
 
-    pub fn apply_to(&self, position: Position) -> Position {
-        match position {
-            (r, c) => match *self {
-                North => (r - 1, c),
-                West => (r, c - 1),
-                South => (r + 1, c),
-                East => (r, c + 1)
-            }
-        }
-    }
-
     pub fn action_for(&self, target: Direction) -> Option<Action> {
         if *self == target {
             Some(MoveForward)
@@ -52,6 +48,80 @@ impl Direction {
             None
         }
     }
+
+    pub fn symbol(&self) -> char {
+        match *self {
+            North => 'N',
+            East => 'E',
+            South => 'S',
+            West => 'W'
+        }
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Direction> {
+        match symbol {
+            'N' => Some(North),
+            'E' => Some(East),
+            'S' => Some(South),
+            'W' => Some(West),
+            _ => None
+        }
+    }
+}
+
+/// What happens at the edges of the board.
+#[deriving(PartialEq, Eq, Show, Clone)]
+pub enum Topology {
+    Bounded, // Driving off the board crashes.
+    WrapHorizontal, // The left and right edges are joined.
+    WrapVertical, // The top and bottom edges are joined.
+    Torus // Both pairs of edges are joined.
+}
+
+impl Topology {
+    pub fn wraps_horizontally(&self) -> bool {
+        *self == WrapHorizontal || *self == Torus
+    }
+
+    pub fn wraps_vertically(&self) -> bool {
+        *self == WrapVertical || *self == Torus
+    }
+
+    /// The cell one step from `position` in `direction` on a `width` by
+    /// `height` board, or `None` if that leaves the board.
+    pub fn step(&self, position: Position, direction: Direction, width: uint, height: uint) -> Option<Position> {
+        let (r, c) = position;
+        match direction {
+            North if r > 0 => Some((r - 1, c)),
+            North if self.wraps_vertically() => Some((height - 1, c)),
+            South if r + 1 < height => Some((r + 1, c)),
+            South if self.wraps_vertically() => Some((0, c)),
+            West if c > 0 => Some((r, c - 1)),
+            West if self.wraps_horizontally() => Some((r, width - 1)),
+            East if c + 1 < width => Some((r, c + 1)),
+            East if self.wraps_horizontally() => Some((r, 0)),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Bounded => "bounded",
+            WrapHorizontal => "wrap-horizontal",
+            WrapVertical => "wrap-vertical",
+            Torus => "torus"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Topology> {
+        match name {
+            "bounded" => Some(Bounded),
+            "wrap-horizontal" => Some(WrapHorizontal),
+            "wrap-vertical" => Some(WrapVertical),
+            "torus" => Some(Torus),
+            _ => None
+        }
+    }
 }
 
 #[deriving(PartialEq, Eq, Show, Clone)]
@@ -62,13 +132,30 @@ pub enum Action {
 }
 
 impl Action {
-    fn apply_to(&self, direction : Direction) -> Direction {
+    pub fn apply_to(&self, direction : Direction) -> Direction {
         match *self {
             MoveForward => direction,
             TurnLeft => direction.left(),
             TurnRight => direction.right()
         }
     }
+
+    pub fn symbol(&self) -> char {
+        match *self {
+            MoveForward => 'F',
+            TurnLeft => 'L',
+            TurnRight => 'R'
+        }
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Action> {
+        match symbol {
+            'F' => Some(MoveForward),
+            'L' => Some(TurnLeft),
+            'R' => Some(TurnRight),
+            _ => None
+        }
+    }
 }
 
 pub type PlayerIndex = uint;
@@ -81,26 +168,194 @@ pub struct Player {
     pub is_alive: bool
 }
 
+/// Places `count` players on an ellipse around the centre of the board, each
+/// heading clockwise so that nobody starts off driving into someone else.
+pub fn default_players(count: uint, board_width: uint, board_height: uint) -> Vec<Player> {
+    players_on_ellipse(count, board_width, board_height, 0.0)
+}
+
+/// Like `default_players`, with the starting points turned by `rotation`
+/// radians around the centre of the board.
+pub fn players_on_ellipse(count: uint, board_width: uint, board_height: uint, rotation: f64) -> Vec<Player> {
+    let (center_r, center_c) = ((board_height / 2) as f64, (board_width / 2) as f64);
+    let (radius_r, radius_c) = ((board_height / 4) as f64, (board_width / 4) as f64);
+    range(0, count).map(|i| {
+        let angle = rotation + f64::consts::PI * (1.0 + 2.0 * (i as f64) / (count as f64));
+        let position = ((center_r + radius_r * angle.sin()).round() as uint,
+                        (center_c + radius_c * angle.cos()).round() as uint);
+        let direction = if angle.cos().abs() >= angle.sin().abs() {
+            if angle.cos() > 0.0 { South } else { North }
+        } else {
+            if angle.sin() > 0.0 { West } else { East }
+        };
+        Player {
+            name: format!("Player {}", i + 1),
+            position: position,
+            direction: direction,
+            is_alive: true
+        }
+    }).collect()
+}
+
+/// A player's decision making, run on its own task by `Behaviour::from_bot`.
+pub trait Bot {
+    /// Called with the first state the bot sees in a game. A bot plays
+    /// every game of a series, so this is where it forgets the last one.
+    fn new_game(&mut self, _me: PlayerIndex, _game: &GameState) {}
+
+    /// Chooses player `me`'s action in `game`. Answers arriving after
+    /// `deadline`, a `precise_time_ns` value, are ignored.
+    fn act(&mut self, me: PlayerIndex, game: &GameState, deadline: u64) -> Action;
+
+    /// Called with the final state once the game is over.
+    fn game_over(&mut self, _me: PlayerIndex, _game: &GameState) {}
+
+    /// Whether the bot wants to think during other players' turns. Such
+    /// bots are also sent the states in which they do not move.
+    fn ponders(&self) -> bool { false }
+
+    /// Thinks a little about `game`, the latest state seen, while waiting
+    /// for the next one. Returns `false` once there is nothing left to do.
+    fn ponder(&mut self, _me: PlayerIndex, _game: &GameState) -> bool { false }
+}
+
+/// Tells apart the games a behaviour plays, so that a late answer from one
+/// game is not taken for a move in the next.
+pub type GameId = uint;
+
+/// What behaviours are sent: the game, the player they control, the latest
+/// state and the milliseconds they have to answer if the player is to move.
+pub type StateMessage = (GameId, PlayerIndex, GameState, i64);
+
+/// What behaviours answer: the game and turn of the state answered, and
+/// the action.
+pub type ActionMessage = (GameId, uint, Action);
+
+/// Lets `bot` ponder `game` until the next state arrives, and returns it,
+/// or `None` once no more states can arrive.
+fn ponder_until_received<B: Bot>(bot: &mut B, me: PlayerIndex, game: &GameState,
+                                 receiver: &Receiver<StateMessage>) -> Option<StateMessage> {
+    loop {
+        match receiver.try_recv() {
+            Ok(message) => return Some(message),
+            Err(comm::Disconnected) => return None,
+            Err(comm::Empty) => {}
+        }
+        if !bot.ponder(me, game) {
+            return receiver.recv_opt().ok();
+        }
+    }
+}
+
+/// The message a task failed with.
+fn failure_message(cause: &Box<Any + Send>) -> String {
+    match cause.downcast_ref::<&'static str>() {
+        Some(message) => message.to_string(),
+        None => match cause.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown failure".to_string()
+        }
+    }
+}
+
 pub struct Behaviour {
-    sender: Sender<GameState>,
-    pub receiver: Receiver<(uint, Action)>
+    sender: Sender<StateMessage>,
+    pub receiver: Receiver<ActionMessage>,
+    game: Cell<GameId>, // The game states are sent for.
+    failure: Receiver<String>, // Why the behaviour's task failed, if it did.
+    pub ponders: bool // Whether to send it the states in which it does not move.
 }
 
 impl Behaviour {
-    pub fn make(body: proc (Receiver<GameState>, Sender<(uint, Action)>): Send) -> Behaviour {
-        let (state_sender, state_receiver) = channel::<GameState>();
-        let (action_sender, action_receiver) = channel::<(uint, Action)>();
+    /// Runs `body` on its own task. If the task fails, its action channel
+    /// closes and the failure message can be read with `failure_reason`.
+    pub fn make(body: proc (Receiver<StateMessage>, Sender<ActionMessage>): Send) -> Behaviour {
+        let (state_sender, state_receiver) = channel::<StateMessage>();
+        let (action_sender, action_receiver) = channel::<ActionMessage>();
+        let (failure_sender, failure_receiver) = channel::<String>();
         spawn(proc () {
-            body(state_receiver, action_sender);
+            match task::try(proc () { body(state_receiver, action_sender) }) {
+                Ok(()) => {}
+                Err(cause) => {
+                    let _ = failure_sender.send_opt(failure_message(&cause));
+                }
+            }
         });
         Behaviour {
             sender: state_sender,
-            receiver: action_receiver
+            receiver: action_receiver,
+            game: Cell::new(0),
+            failure: failure_receiver,
+            ponders: false
+        }
+    }
+
+    /// Why the behaviour stopped, once its action channel has closed.
+    pub fn failure_reason(&self) -> String {
+        match self.failure.recv_opt() {
+            Ok(reason) => reason,
+            Err(()) => "stopped answering".to_string()
         }
     }
 
-    pub fn send_state(&self, game: &GameState) {
-        self.sender.send(game.clone());
+    /// Runs `bot` on its own task until the behaviour is dropped, so that it
+    /// can play several games in a row. Bots that ponder think about the
+    /// latest state whenever no new one is waiting.
+    pub fn from_bot<B: Bot + Send>(bot: B) -> Behaviour {
+        let ponders = bot.ponders();
+        let mut behaviour = Behaviour::make(proc(state_receiver, action_sender) {
+            let mut bot = bot;
+            let mut started = false;
+            let mut latest: Option<(PlayerIndex, GameState)> = None;
+            loop {
+                let message = match latest.take() {
+                    Some((me, game)) => ponder_until_received(&mut bot, me, &game, &state_receiver),
+                    None => state_receiver.recv_opt().ok()
+                };
+                let (id, me, game, time_ms) = match message {
+                    Some(message) => message,
+                    None => break
+                };
+                let deadline = precise_time_ns() + (time_ms as u64) * 1000000;
+                if !started {
+                    bot.new_game(me, &game);
+                    started = true;
+                }
+                if game.is_over() {
+                    debug!("Game is over, waiting for the next one.");
+                    bot.game_over(me, &game);
+                    started = false;
+                    continue;
+                };
+                if game.acting_players().contains(&me) {
+                    let action = bot.act(me, &game, deadline);
+                    debug!("Sending action {}", action);
+                    action_sender.send((id, game.turn, action));
+                }
+                if ponders {
+                    latest = Some((me, game));
+                }
+            }
+        });
+        behaviour.ponders = ponders;
+        behaviour
+    }
+
+    /// Moves on to a new game, whose states and answers are told apart from
+    /// the previous ones by the game id.
+    pub fn start_game(&self) {
+        self.game.set(self.game.get() + 1);
+    }
+
+    pub fn game_id(&self) -> GameId {
+        self.game.get()
+    }
+
+    /// Sends the behaviour of `player` the state `game`, giving it
+    /// `time_ms` milliseconds to answer if `player` is to move. States sent
+    /// to a behaviour that has stopped are dropped.
+    pub fn send_state(&self, player: PlayerIndex, game: &GameState, time_ms: i64) {
+        let _ = self.sender.send_opt((self.game.get(), player, game.clone(), time_ms));
     }
 }
 
@@ -109,7 +364,8 @@ pub enum Tile {
     Empty,
     PlayerWall(PlayerIndex),
     PlayerHead(PlayerIndex),
-    Crash
+    Crash,
+    Obstacle // Part of the map, there from the start.
 }
 
 impl Tile {
@@ -121,16 +377,26 @@ impl Tile {
     }
 }
 
+#[deriving(PartialEq, Eq, Show, Clone)]
+pub enum Rules {
+    /// Players move one after another in index order.
+    Alternating,
+    /// All living players choose an action for the same tick and move at once.
+    Simultaneous
+}
+
 #[deriving(PartialEq, Eq, Show, Clone)]
 pub enum GameStatus {
     PlayerTurn(PlayerIndex),
-    Won(PlayerIndex)
+    AllPlayersTurn,
+    Won(PlayerIndex),
+    Draw(Vec<PlayerIndex>) // Players sharing first place.
 }
 
 impl GameStatus {
     pub fn is_over(&self) -> bool {
         match *self {
-            PlayerTurn(_) => false,
+            PlayerTurn(_) | AllPlayersTurn => false,
             _ => true
         }
     }
@@ -142,18 +408,32 @@ pub struct GameState {
     pub players: Vec<Player>,
     pub alive_count: uint,
     pub status: GameStatus,
+    pub rules: Rules,
+    pub turn_limit: Option<uint>, // Turn at which the surviving players draw.
+    pub topology: Topology,
+    pub death_turns: Vec<Option<uint>>,
+    pub hash: u64, // Zobrist hash of the board, directions and player to move.
     pub board_width: uint,
     pub board_height: uint,
     pub board: Vec<Vec<Tile>>
 }
 
 impl GameState {
-    pub fn new(board_width: uint, board_height: uint, players: Vec<Player>) -> GameState {
+    pub fn new(board_width: uint, board_height: uint, players: Vec<Player>, rules: Rules) -> GameState {
+        let player_count = players.len();
         let mut s = GameState {
             turn: 0,
             players: players,
-            alive_count: 2,
-            status: PlayerTurn(0),
+            alive_count: player_count,
+            status: match rules {
+                Alternating => PlayerTurn(0),
+                Simultaneous => AllPlayersTurn
+            },
+            rules: rules,
+            turn_limit: None,
+            topology: Bounded,
+            death_turns: Vec::from_elem(player_count, None),
+            hash: 0,
             board_width: board_width,
             board_height: board_height,
             board: Vec::from_elem(board_height, Vec::from_elem(board_width, Empty))
@@ -161,17 +441,41 @@ impl GameState {
         // Place initial walls.
         for i in range(0, s.alive_count) {
             let pos = s.players[i].position;
-            s.board_set(pos, PlayerHead(i))
+            s.board_set(pos, PlayerHead(i));
+            s.hash ^= zobrist::direction_key(i, s.players[i].direction);
         };
+        s.hash ^= zobrist::status_key(&s.status);
         s
     }
 
     fn board_set(&mut self, position: Position, tile: Tile) {
         match position {
-            (r, c) => self.board.get_mut(r).grow_set(c, &Empty, tile)
+            (r, c) => {
+                self.hash ^= zobrist::tile_key(position, &self.board[r][c]) ^ zobrist::tile_key(position, &tile);
+                self.board.get_mut(r).grow_set(c, &Empty, tile)
+            }
         }
     }
 
+    fn set_status(&mut self, status: GameStatus) {
+        self.hash ^= zobrist::status_key(&self.status) ^ zobrist::status_key(&status);
+        self.status = status;
+    }
+
+    fn set_direction(&mut self, player: PlayerIndex, direction: Direction) {
+        self.hash ^= zobrist::direction_key(player, self.players[player].direction) ^
+            zobrist::direction_key(player, direction);
+        self.players.get_mut(player).direction = direction;
+    }
+
+    fn kill(&mut self, player: PlayerIndex) {
+        let position = self.players[player].position;
+        self.board_set(position, Crash);
+        self.players.get_mut(player).is_alive = false;
+        *self.death_turns.get_mut(player) = Some(self.turn);
+        self.alive_count -= 1;
+    }
+
     fn move_to(&mut self, player: PlayerIndex, position: Position) {
         let old_pos = self.players[player].position;
         self.board_set(old_pos, PlayerWall(player));
@@ -179,6 +483,32 @@ impl GameState {
         self.players.get_mut(player).position = position;
     }
 
+    /// Blocks the empty cell at `position`, as maps do before the game starts.
+    pub fn place_obstacle(&mut self, position: Position) {
+        let (r, c) = position;
+        assert!(self.board[r][c] == Empty);
+        self.board_set(position, Obstacle);
+    }
+
+    /// Cells blocked by the map, row by row.
+    pub fn obstacles(&self) -> Vec<Position> {
+        let mut obstacles = vec![];
+        for (r, row) in self.board.iter().enumerate() {
+            for (c, tile) in row.iter().enumerate() {
+                if *tile == Obstacle {
+                    obstacles.push((r, c));
+                }
+            }
+        }
+        obstacles
+    }
+
+    /// The cell one step from `position` in `direction` under the board's
+    /// topology, or `None` if that leaves the board.
+    pub fn step(&self, position: Position, direction: Direction) -> Option<Position> {
+        self.topology.step(position, direction, self.board_width, self.board_height)
+    }
+
     pub fn can_move_to(&self, position: Position) -> bool {
         match position {
             (row, column) => {
@@ -189,6 +519,11 @@ impl GameState {
         }
     }
 
+    /// Whether a player at `position` can take one step in `direction`.
+    pub fn can_move(&self, position: Position, direction: Direction) -> bool {
+        self.step(position, direction).map_or(false, |target| self.can_move_to(target))
+    }
+
     pub fn player_after(&self, current: PlayerIndex) -> PlayerIndex {
         assert!(self.alive_count >= 1);
         let mut cur = (current + 1) % self.players.len();
@@ -198,10 +533,22 @@ impl GameState {
         cur
     }
 
+    pub fn opponents(&self, player: PlayerIndex) -> Vec<PlayerIndex> {
+        range(0, self.players.len()).filter(|&i| {
+            i != player && self.players[i].is_alive
+        }).collect()
+    }
+
     pub fn is_over(&self) -> bool {
+        self.status.is_over()
+    }
+
+    /// Players whose action is awaited before the game can advance.
+    pub fn acting_players(&self) -> Vec<PlayerIndex> {
         match self.status {
-            PlayerTurn(_) => false,
-            _ => true
+            PlayerTurn(x) => vec![x],
+            AllPlayersTurn => range(0, self.players.len()).filter(|&i| self.players[i].is_alive).collect(),
+            _ => vec![]
         }
     }
 
@@ -212,37 +559,157 @@ impl GameState {
         }
     }
 
-    pub fn winner(&self) -> PlayerIndex {
+    /// The sole winner, if the game is over and was not drawn.
+    pub fn winner(&self) -> Option<PlayerIndex> {
+        match self.status {
+            Won(x) => Some(x),
+            _ => None
+        }
+    }
+
+    /// Players sharing first place; empty while the game is in progress.
+    pub fn winners(&self) -> Vec<PlayerIndex> {
         match self.status {
-            Won(x) => x,
-            _ => fail!("GameState::winner called before game over")
+            Won(x) => vec![x],
+            Draw(ref players) => players.clone(),
+            _ => vec![]
         }
     }
 
+    /// Finishing order, best first. Players still alive share the top
+    /// place, the rest are ordered by how long they survived, and players
+    /// that crashed on the same turn share a place.
+    pub fn ranking(&self) -> Vec<Vec<PlayerIndex>> {
+        let mut places: Vec<Vec<PlayerIndex>> = vec![];
+        let alive: Vec<PlayerIndex> = range(0, self.players.len()).filter(|&i| self.players[i].is_alive).collect();
+        if !alive.is_empty() {
+            places.push(alive);
+        }
+        let mut dead: Vec<(uint, PlayerIndex)> = range(0, self.players.len()).filter_map(|i| {
+            self.death_turns[i].map(|turn| (turn, i))
+        }).collect();
+        dead.sort_by(|&(a, _), &(b, _)| b.cmp(&a));
+        let mut last_turn = None;
+        for &(turn, player) in dead.iter() {
+            if last_turn == Some(turn) {
+                places.mut_last().unwrap().push(player);
+            } else {
+                places.push(vec![player]);
+                last_turn = Some(turn);
+            }
+        }
+        places
+    }
+
+    /// Ends the game in a draw between the survivors once the turn limit
+    /// has been reached.
+    fn check_turn_limit(&mut self) {
+        match self.turn_limit {
+            Some(limit) if self.turn >= limit && !self.is_over() => {
+                let survivors = range(0, self.players.len()).filter(|&i| self.players[i].is_alive).collect();
+                self.set_status(Draw(survivors));
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances an alternating game by the current player's action.
     pub fn do_turn(&mut self, action: Action) {
         let current = self.current_player();
         let cur_direction = self.players[current].direction;
         let new_direction = action.apply_to(cur_direction);
-        self.players.get_mut(current).direction = new_direction;
+        self.set_direction(current, new_direction);
         let cur_position = self.players[current].position;
-        let new_position = new_direction.apply_to(cur_position);
 
-        if self.can_move_to(new_position) {
-            self.move_to(current, new_position);
-        } else {
-            self.board_set(cur_position, Crash);
-            self.players.get_mut(current).is_alive = false;
-            self.alive_count -= 1;
+        match self.step(cur_position, new_direction) {
+            Some(new_position) if self.can_move_to(new_position) => self.move_to(current, new_position),
+            _ => self.kill(current)
         }
 
         let next_player = self.player_after(current);
         if self.alive_count == 1 {
-            self.status = Won(next_player)
+            self.set_status(Won(next_player))
         } else {
-            self.status = PlayerTurn(next_player)
+            self.set_status(PlayerTurn(next_player))
+        }
+
+        self.turn += 1;
+        self.check_turn_limit();
+    }
+
+    /// Advances a simultaneous game by one tick. `actions` is indexed by
+    /// player; entries of players that are already dead are ignored.
+    pub fn do_tick(&mut self, actions: &[Action]) {
+        assert!(self.status == AllPlayersTurn);
+        let moving = self.acting_players();
+        let targets: Vec<Option<Position>> = moving.iter().map(|&p| {
+            let new_direction = actions[p].apply_to(self.players[p].direction);
+            self.set_direction(p, new_direction);
+            self.step(self.players[p].position, new_direction)
+        }).collect();
+
+        // Two players swapping cells run into each other's heads, which
+        // `can_move_to` already rejects; head-on collisions into the same
+        // empty cell need checking against the other targets.
+        let crashed: Vec<bool> = targets.iter().enumerate().map(|(i, target)| {
+            !target.map_or(false, |target| self.can_move_to(target)) ||
+                targets.iter().enumerate().any(|(j, other)| i != j && other == target)
+        }).collect();
+
+        for (i, &p) in moving.iter().enumerate() {
+            if !crashed[i] {
+                self.move_to(p, targets[i].unwrap());
+            }
+        }
+        let mut died = vec![];
+        for (i, &p) in moving.iter().enumerate() {
+            if crashed[i] {
+                match targets[i] {
+                    Some(target) if self.can_move_to(target) => self.board_set(target, Crash),
+                    _ => {}
+                }
+                self.kill(p);
+                died.push(p);
+            }
         }
 
+        let status = match self.alive_count {
+            0 => Draw(died),
+            1 => Won(self.acting_players()[0]),
+            _ => AllPlayersTurn
+        };
+        self.set_status(status);
+
         self.turn += 1;
+        self.check_turn_limit();
+    }
+
+    /// Takes `players` out of the game at once without a move, as if they
+    /// had crashed where they stand, e.g. for running out of time. In
+    /// alternating games eliminating the player to move ends its turn.
+    pub fn eliminate(&mut self, players: &[PlayerIndex]) {
+        let to_move = match self.status {
+            PlayerTurn(p) => Some(p),
+            _ => None
+        };
+        for &p in players.iter() {
+            if self.players[p].is_alive {
+                self.kill(p);
+            }
+        }
+
+        let status = match (self.alive_count, to_move) {
+            (0, _) => Draw(players.to_vec()),
+            (1, _) => Won(range(0, self.players.len()).find(|&i| self.players[i].is_alive).unwrap()),
+            (_, Some(p)) if !self.players[p].is_alive => {
+                self.turn += 1;
+                PlayerTurn(self.player_after(p))
+            }
+            (_, Some(p)) => PlayerTurn(p),
+            (_, None) => AllPlayersTurn
+        };
+        self.set_status(status);
+        self.check_turn_limit();
     }
 
     pub fn apply_action(&self, action: Action) -> GameState {
@@ -250,4 +717,21 @@ impl GameState {
         new_game.do_turn(action);
         new_game
     }
+
+    /// Returns a copy of the game under alternating rules with `player` to
+    /// move, so that turn-based search can also be used in simultaneous
+    /// games (pessimistically letting opponents react to our move). A
+    /// simultaneous turn limit counts ticks, which the view plays out as one
+    /// turn per living player.
+    pub fn sequential_view(&self, player: PlayerIndex) -> GameState {
+        let mut view = self.clone();
+        if self.rules == Simultaneous {
+            view.turn_limit = self.turn_limit.map(|limit| {
+                if limit > self.turn { self.turn + (limit - self.turn) * self.alive_count } else { limit }
+            });
+        }
+        view.rules = Alternating;
+        view.set_status(PlayerTurn(player));
+        view
+    }
 }
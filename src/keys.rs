@@ -0,0 +1,92 @@
+//! Keys that steer keyboard-controlled players.
+//!
+//! Every keyboard player has its own bindings so that several people can
+//! play on one terminal. Bindings are written as the arrow keys (`arrows`),
+//! the vi keys (`vi`, hjkl) or four characters for up, left, down and right
+//! as in `wasd`, and can be combined with `+`, as in `arrows+vi`.
+
+use game::{Direction, North, East, South, West};
+use ncurses;
+
+/// Key that quits the match; no player may steer with it.
+static QUIT_KEY: char = 'q';
+
+#[deriving(Clone, Show, PartialEq)]
+pub struct KeyBindings {
+    pub spec: String, // As given on the command line.
+    keys: Vec<(i32, Direction)>
+}
+
+impl KeyBindings {
+    /// The direction `key` steers towards, if it is bound.
+    pub fn direction(&self, key: i32) -> Option<Direction> {
+        self.keys.iter().find(|&&(bound, _)| bound == key).map(|&(_, direction)| direction)
+    }
+
+    /// Whether the two bindings share a key.
+    pub fn overlaps(&self, other: &KeyBindings) -> bool {
+        self.keys.iter().any(|&(key, _)| other.direction(key).is_some())
+    }
+}
+
+fn bind_char(keys: &mut Vec<(i32, Direction)>, key: char, direction: Direction) {
+    keys.push((key as i32, direction));
+    if key.is_alphabetic() {
+        // Steer the same with caps lock on.
+        keys.push((key.to_uppercase() as i32, direction));
+    }
+}
+
+/// Parses bindings as described in the module documentation.
+pub fn parse_key_bindings(spec: &str) -> Result<KeyBindings, String> {
+    let mut keys = vec![];
+    for part in spec.split('+') {
+        match part {
+            "arrows" => {
+                keys.push((ncurses::KEY_UP, North));
+                keys.push((ncurses::KEY_LEFT, West));
+                keys.push((ncurses::KEY_DOWN, South));
+                keys.push((ncurses::KEY_RIGHT, East));
+            }
+            "vi" => {
+                bind_char(&mut keys, 'k', North);
+                bind_char(&mut keys, 'h', West);
+                bind_char(&mut keys, 'j', South);
+                bind_char(&mut keys, 'l', East);
+            }
+            custom if custom.char_len() == 4 => {
+                for (key, &direction) in custom.chars().zip([North, West, South, East].iter()) {
+                    if key <= ' ' || key > '~' {
+                        return Err(format!("key bindings {}: only printable ASCII keys can be bound", spec));
+                    }
+                    if key.to_lowercase() == QUIT_KEY {
+                        return Err(format!("key bindings {}: {} quits the match", spec, key));
+                    }
+                    bind_char(&mut keys, key.to_lowercase(), direction);
+                }
+            }
+            _ => return Err(format!("unknown key bindings {}, expected arrows, vi or four keys for up, left, \
+                                     down and right such as wasd, joined by +", spec))
+        }
+    }
+    for (i, &(key, _)) in keys.iter().enumerate() {
+        if keys.slice_to(i).iter().any(|&(other, _)| other == key) {
+            return Err(format!("key bindings {} use a key twice", spec));
+        }
+    }
+    Ok(KeyBindings {
+        spec: spec.to_string(),
+        keys: keys
+    })
+}
+
+/// Bindings of the `index`th keyboard player when none are given: the
+/// arrow and vi keys for the first and wasd for the second.
+pub fn default_key_bindings(index: uint) -> Option<KeyBindings> {
+    let spec = match index {
+        0 => "arrows+vi",
+        1 => "wasd",
+        _ => return None
+    };
+    Some(parse_key_bindings(spec).unwrap())
+}
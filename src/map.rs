@@ -0,0 +1,199 @@
+//! Arenas with static obstacles and fixed start positions.
+//!
+//! A map is a plain text file with a versioned header, an optional name and
+//! the board drawn row by row:
+//!
+//! ```text
+//! ron-map 1
+//! name <name>
+//! board
+//! #########
+//! #>.....<#
+//! #########
+//! ```
+//!
+//! In the board `.` is a free cell, `#` an obstacle and a space a cell
+//! outside the playfield, which is an obstacle as well; rows shorter than
+//! the longest one are padded with such cells. `^`, `>`, `v` and `<` are
+//! free cells where a player starts heading north, east, south or west.
+//! Players are given the start cells in reading order, except that an even
+//! number of players take them in point-symmetric pairs where the map has
+//! them, so that two players on a four-start map face each other fairly.
+
+use game::{Direction, North, East, South, West, GameState, Player, Position, Rules};
+use std::io::{File, BufferedReader};
+
+static FORMAT_VERSION: uint = 1;
+
+/// Maps built into the program, by name.
+static BUNDLED: [(&'static str, &'static str), ..4] = [
+    ("arena", include_str!("../maps/arena.map")),
+    ("pillars", include_str!("../maps/pillars.map")),
+    ("maze", include_str!("../maps/maze.map")),
+    ("diamond", include_str!("../maps/diamond.map"))
+];
+
+#[deriving(Clone, Show)]
+pub struct Map {
+    pub name: String,
+    pub width: uint,
+    pub height: uint,
+    pub obstacles: Vec<Position>,
+    pub starts: Vec<(Position, Direction)> // In reading order.
+}
+
+/// Names of the maps built into the program.
+pub fn bundled_names() -> Vec<&'static str> {
+    BUNDLED.iter().map(|&(name, _)| name).collect()
+}
+
+impl Map {
+    /// Finds a bundled map called `name`, or else loads the file at `name`.
+    pub fn find(name: &str) -> Result<Map, String> {
+        match BUNDLED.iter().find(|&&(bundled, _)| bundled == name) {
+            Some(&(_, text)) => Map::parse(text.lines().collect::<Vec<&str>>().as_slice())
+                .map_err(|e| format!("map {}: {}", name, e)),
+            None => Map::load(&Path::new(name))
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Map, String> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("{}: {}", path.display(), e))
+        };
+        let mut reader = BufferedReader::new(file);
+        let mut lines = vec![];
+        for line in reader.lines() {
+            match line {
+                Ok(line) => lines.push(line),
+                Err(e) => return Err(format!("{}: {}", path.display(), e))
+            }
+        }
+        Map::parse(lines.iter().map(|line| line.as_slice()).collect::<Vec<&str>>().as_slice())
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(lines: &[&str]) -> Result<Map, String> {
+        let mut map = Map {
+            name: "unnamed".to_string(),
+            width: 0,
+            height: 0,
+            obstacles: vec![],
+            starts: vec![]
+        };
+        let mut version_seen = false;
+        let mut board_start = None;
+        for (number, line) in lines.iter().enumerate() {
+            let words: Vec<&str> = line.words().collect();
+            let error = |message: &str| Err(format!("line {}: {}", number + 1, message));
+            if words.is_empty() {
+                continue;
+            }
+            if !version_seen {
+                if words.len() != 2 || words[0] != "ron-map" || from_str::<uint>(words[1]) != Some(FORMAT_VERSION) {
+                    return error("not a supported map file");
+                }
+                version_seen = true;
+                continue;
+            }
+            match (words[0], words.len()) {
+                ("name", n) if n >= 2 => map.name = words.slice_from(1).connect(" "),
+                ("board", 1) => {
+                    board_start = Some(number + 1);
+                    break;
+                }
+                _ => return error("expected name or board")
+            }
+        }
+
+        let first_row = match board_start {
+            Some(first_row) => first_row,
+            None => return Err("missing board".to_string())
+        };
+        let rows: Vec<&str> = lines.slice_from(first_row).iter().map(|row| row.trim_right()).collect();
+        let height = rows.iter().rposition(|row| !row.is_empty()).map_or(0, |last| last + 1);
+        map.height = height;
+        map.width = rows.slice_to(height).iter().map(|row| row.char_len()).max().unwrap_or(0);
+        for (r, row) in rows.slice_to(height).iter().enumerate() {
+            let error = |message: &str| Err(format!("line {}: {}", first_row + r + 1, message));
+            for (c, symbol) in row.chars().enumerate() {
+                let start = match symbol {
+                    '^' => Some(North),
+                    '>' => Some(East),
+                    'v' => Some(South),
+                    '<' => Some(West),
+                    _ => None
+                };
+                match (symbol, start) {
+                    (_, Some(direction)) => map.starts.push(((r, c), direction)),
+                    ('.', _) => {}
+                    ('#', _) | (' ', _) => map.obstacles.push((r, c)),
+                    _ => return error(format!("unknown cell {}", symbol).as_slice())
+                }
+            }
+            for c in range(row.char_len(), map.width) {
+                map.obstacles.push((r, c));
+            }
+        }
+        if map.starts.len() < 2 {
+            return Err("a map needs at least two start cells".to_string());
+        }
+        Ok(map)
+    }
+
+    /// Start `i`'s point-symmetric partner, heading the opposite way.
+    fn opposite_start(&self, i: uint) -> Option<uint> {
+        let ((r, c), direction) = self.starts[i];
+        let opposite = ((self.height - 1 - r, self.width - 1 - c), direction.right().right());
+        self.starts.iter().position(|&start| start == opposite).and_then(|j| if j != i { Some(j) } else { None })
+    }
+
+    /// The start cells of `count` players, in pairs of point-symmetric
+    /// starts if `count` is even and the map has enough of them, and
+    /// otherwise the first `count` in reading order.
+    fn start_order(&self, count: uint) -> Vec<uint> {
+        if count % 2 == 0 {
+            let mut order = vec![];
+            for i in range(0, self.starts.len()) {
+                if order.len() == count {
+                    break;
+                }
+                match self.opposite_start(i) {
+                    Some(j) if !order.contains(&i) && !order.contains(&j) => {
+                        order.push(i);
+                        order.push(j);
+                    }
+                    _ => {}
+                }
+            }
+            if order.len() == count {
+                return order;
+            }
+        }
+        range(0, count).collect()
+    }
+
+    /// Players on `count` of the start cells, chosen by `start_order`.
+    pub fn players(&self, count: uint) -> Vec<Player> {
+        assert!(count <= self.starts.len());
+        self.start_order(count).iter().enumerate().map(|(i, &start)| {
+            let (position, direction) = self.starts[start];
+            Player {
+                name: format!("Player {}", i + 1),
+                position: position,
+                direction: direction,
+                is_alive: true
+            }
+        }).collect()
+    }
+
+    /// A game on this map between `players`.
+    pub fn new_game(&self, players: Vec<Player>, rules: Rules) -> GameState {
+        let mut game = GameState::new(self.width, self.height, players, rules);
+        for &position in self.obstacles.iter() {
+            game.place_obstacle(position);
+        }
+        game
+    }
+}
@@ -0,0 +1,372 @@
+//! Match recordings.
+//!
+//! A replay is a plain text file with a versioned header describing the
+//! initial position, including the map's obstacles and the board's
+//! topology, and the reasons any behaviours failed, followed by one line per
+//! turn and one per elimination of players that ran out of time or whose
+//! behaviour failed:
+//!
+//! ```text
+//! ron-replay 5
+//! board <width> <height>
+//! obstacles <row> <column> ...
+//! topology bounded|wrap-horizontal|wrap-vertical|torus
+//! rules alternating|simultaneous
+//! turn-limit <turns>|none
+//! seed <seed>
+//! player <row> <column> <N|E|S|W> <name>
+//! ...
+//! failure <player index> <turn> <reason>
+//! ...
+//! turn <F|L|R> ...
+//! eliminate <player index> ...
+//! ```
+//!
+//! Under alternating rules a turn line holds the action of the player to
+//! move; under simultaneous rules it holds one action per player, with `-`
+//! for players that are no longer alive. There is one obstacles line per row
+//! with obstacles in it. Files from earlier versions, which lack some of
+//! these lines, can still be read.
+
+use game::{Action, MoveForward, Direction, GameState, Player, PlayerIndex, Position};
+use game::{Rules, Alternating, Simultaneous, Topology, Bounded};
+use std::io::{File, BufferedReader, IoResult};
+
+static FORMAT_VERSION: uint = 5;
+
+#[deriving(Clone, Show)]
+pub enum Event {
+    Moves(Vec<Action>), // The actions played on a turn.
+    Eliminated(Vec<PlayerIndex>) // Players taken out of the game without a move.
+}
+
+/// A behaviour that crashed or hung, taking its player out of the match.
+#[deriving(Clone, Show)]
+pub struct Failure {
+    pub player: PlayerIndex,
+    pub turn: uint,
+    pub reason: String
+}
+
+#[deriving(Clone, Show)]
+pub struct Replay {
+    pub board_width: uint,
+    pub board_height: uint,
+    pub obstacles: Vec<Position>,
+    pub topology: Topology,
+    pub rules: Rules,
+    pub turn_limit: Option<uint>,
+    pub seed: u64,
+    pub players: Vec<Player>,
+    pub failures: Vec<Failure>,
+    pub events: Vec<Event>
+}
+
+impl Replay {
+    /// Starts a recording of a match beginning at `game`.
+    pub fn new(game: &GameState, seed: u64) -> Replay {
+        Replay {
+            board_width: game.board_width,
+            board_height: game.board_height,
+            obstacles: game.obstacles(),
+            topology: game.topology.clone(),
+            rules: game.rules.clone(),
+            turn_limit: game.turn_limit,
+            seed: seed,
+            players: game.players.clone(),
+            failures: vec![],
+            events: vec![]
+        }
+    }
+
+    /// Records the actions taken on the turn about to be played in `game`.
+    /// `actions` is indexed by player, as for `GameState::do_tick`.
+    pub fn record_turn(&mut self, game: &GameState, actions: &[Action]) {
+        self.events.push(Moves(match self.rules {
+            Alternating => vec![actions[game.current_player()]],
+            Simultaneous => actions.to_vec()
+        }));
+    }
+
+    /// Records that `players` are taken out of the game, as by `GameState::eliminate`.
+    pub fn record_elimination(&mut self, players: &[PlayerIndex]) {
+        self.events.push(Eliminated(players.to_vec()));
+    }
+
+    /// Records why a player's behaviour failed. The player's elimination is
+    /// recorded separately.
+    pub fn record_failure(&mut self, failure: Failure) {
+        self.failures.push(failure);
+    }
+
+    pub fn initial_state(&self) -> GameState {
+        let mut game = GameState::new(self.board_width, self.board_height, self.players.clone(), self.rules.clone());
+        game.turn_limit = self.turn_limit;
+        game.topology = self.topology.clone();
+        for &position in self.obstacles.iter() {
+            game.place_obstacle(position);
+        }
+        game
+    }
+
+    /// Every state of the match, from the initial position to the state
+    /// after the last recorded event.
+    pub fn states(&self) -> Vec<GameState> {
+        let mut game = self.initial_state();
+        let mut states = vec![game.clone()];
+        for event in self.events.iter() {
+            if game.is_over() {
+                warn!("Replay continues after the game is over, ignoring the rest.");
+                break;
+            }
+            match *event {
+                Moves(ref actions) => match self.rules {
+                    Alternating => game.do_turn(actions[0]),
+                    Simultaneous => game.do_tick(actions.as_slice())
+                },
+                Eliminated(ref players) => game.eliminate(players.as_slice())
+            }
+            states.push(game.clone());
+        }
+        states
+    }
+
+    pub fn write_to(&self, writer: &mut Writer) -> IoResult<()> {
+        try!(writer.write_str(format!("ron-replay {}\n", FORMAT_VERSION).as_slice()));
+        try!(writer.write_str(format!("board {} {}\n", self.board_width, self.board_height).as_slice()));
+        for r in range(0, self.board_height) {
+            let columns: Vec<String> = self.obstacles.iter().filter(|&&(row, _)| row == r)
+                .map(|&(_, column)| column.to_string()).collect();
+            if !columns.is_empty() {
+                try!(writer.write_str(format!("obstacles {} {}\n", r, columns.connect(" ")).as_slice()));
+            }
+        }
+        try!(writer.write_str(format!("topology {}\n", self.topology.name()).as_slice()));
+        try!(writer.write_str(match self.rules {
+            Alternating => "rules alternating\n",
+            Simultaneous => "rules simultaneous\n"
+        }));
+        try!(writer.write_str(match self.turn_limit {
+            Some(limit) => format!("turn-limit {}\n", limit),
+            None => "turn-limit none\n".to_string()
+        }.as_slice()));
+        try!(writer.write_str(format!("seed {}\n", self.seed).as_slice()));
+        for player in self.players.iter() {
+            let (row, column) = player.position;
+            try!(writer.write_str(format!("player {} {} {} {}\n", row, column,
+                                          player.direction.symbol(), player.name).as_slice()));
+        }
+        for failure in self.failures.iter() {
+            // Keep the reason on one line, and give it at least one word.
+            let reason: Vec<&str> = failure.reason.as_slice().words().collect();
+            let reason = if reason.is_empty() { "unknown".to_string() } else { reason.connect(" ") };
+            try!(writer.write_str(format!("failure {} {} {}\n", failure.player, failure.turn,
+                                          reason).as_slice()));
+        }
+        let states = self.states();
+        for (index, event) in self.events.iter().enumerate() {
+            let line = match *event {
+                Moves(ref actions) => {
+                    let symbols: Vec<String> = actions.iter().enumerate().map(|(i, action)| {
+                        let alive = states.as_slice().get(index).map_or(true, |game| game.players[i].is_alive);
+                        if self.rules == Simultaneous && !alive {
+                            "-".to_string()
+                        } else {
+                            action.symbol().to_string()
+                        }
+                    }).collect();
+                    format!("turn {}\n", symbols.connect(" "))
+                }
+                Eliminated(ref players) => {
+                    let indices: Vec<String> = players.iter().map(|p| p.to_string()).collect();
+                    format!("eliminate {}\n", indices.connect(" "))
+                }
+            };
+            try!(writer.write_str(line.as_slice()));
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> IoResult<()> {
+        let mut file = try!(File::create(path));
+        self.write_to(&mut file)
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("{}: {}", path.display(), e))
+        };
+        let mut reader = BufferedReader::new(file);
+        let mut lines = vec![];
+        for line in reader.lines() {
+            match line {
+                Ok(line) => lines.push(line),
+                Err(e) => return Err(format!("{}: {}", path.display(), e))
+            }
+        }
+        Replay::parse(lines.iter().map(|line| line.as_slice().trim()).collect::<Vec<&str>>().as_slice())
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(lines: &[&str]) -> Result<Replay, String> {
+        let mut replay = Replay {
+            board_width: 0,
+            board_height: 0,
+            obstacles: vec![],
+            topology: Bounded,
+            rules: Alternating,
+            turn_limit: None,
+            seed: 0,
+            players: vec![],
+            failures: vec![],
+            events: vec![]
+        };
+        let mut version_seen = false;
+        for (number, line) in lines.iter().enumerate() {
+            let words: Vec<&str> = line.words().collect();
+            let error = |message: &str| Err(format!("line {}: {}", number + 1, message));
+            if words.is_empty() {
+                continue;
+            }
+            if !version_seen {
+                let version = if words.len() == 2 && words[0] == "ron-replay" { from_str::<uint>(words[1]) } else { None };
+                match version {
+                    Some(version) if version >= 1 && version <= FORMAT_VERSION => {}
+                    _ => return error("not a supported replay file")
+                }
+                version_seen = true;
+                continue;
+            }
+            match (words[0], words.len()) {
+                ("board", 3) => match (from_str(words[1]), from_str(words[2])) {
+                    (Some(width), Some(height)) => {
+                        replay.board_width = width;
+                        replay.board_height = height;
+                    }
+                    _ => return error("invalid board size")
+                },
+                ("obstacles", n) if n >= 3 => {
+                    let numbers: Vec<uint> = words.slice_from(1).iter().filter_map(|word| from_str(*word)).collect();
+                    if numbers.len() != n - 1 || numbers[0] >= replay.board_height ||
+                        numbers.slice_from(1).iter().any(|&column| column >= replay.board_width) {
+                        return error("invalid obstacles");
+                    }
+                    for &column in numbers.slice_from(1).iter() {
+                        replay.obstacles.push((numbers[0], column));
+                    }
+                },
+                ("topology", 2) => match Topology::from_name(words[1]) {
+                    Some(topology) => replay.topology = topology,
+                    None => return error("unknown topology")
+                },
+                ("rules", 2) => replay.rules = match words[1] {
+                    "alternating" => Alternating,
+                    "simultaneous" => Simultaneous,
+                    _ => return error("unknown rules")
+                },
+                ("turn-limit", 2) => replay.turn_limit = match (words[1], from_str(words[1])) {
+                    ("none", _) => None,
+                    (_, Some(limit)) => Some(limit),
+                    _ => return error("invalid turn limit")
+                },
+                ("seed", 2) => match from_str(words[1]) {
+                    Some(seed) => replay.seed = seed,
+                    None => return error("invalid seed")
+                },
+                ("player", n) if n >= 5 => {
+                    // Failures and events refer to players by index.
+                    if !replay.failures.is_empty() || !replay.events.is_empty() {
+                        return error("players must come before failures and turns");
+                    }
+                    let direction = if words[3].len() == 1 {
+                        Direction::from_symbol(words[3].char_at(0))
+                    } else {
+                        None
+                    };
+                    match (from_str(words[1]), from_str(words[2]), direction) {
+                        (Some(row), Some(column), Some(direction)) => replay.players.push(Player {
+                            name: words.slice_from(4).connect(" "),
+                            position: (row, column),
+                            direction: direction,
+                            is_alive: true
+                        }),
+                        _ => return error("invalid player")
+                    }
+                },
+                // Older files may have failures without a reason.
+                ("failure", n) if n >= 3 => match (from_str::<PlayerIndex>(words[1]), from_str(words[2])) {
+                    (Some(player), Some(turn)) if player < replay.players.len() => replay.failures.push(Failure {
+                        player: player,
+                        turn: turn,
+                        reason: words.slice_from(3).connect(" ")
+                    }),
+                    _ => return error("invalid failure")
+                },
+                ("turn", n) if n >= 2 => {
+                    let mut actions = vec![];
+                    for word in words.slice_from(1).iter() {
+                        let action = match *word {
+                            "-" => Some(MoveForward),
+                            w if w.len() == 1 => Action::from_symbol(w.char_at(0)),
+                            _ => None
+                        };
+                        match action {
+                            Some(action) => actions.push(action),
+                            None => return error("invalid action")
+                        }
+                    }
+                    let expected = match replay.rules {
+                        Alternating => 1,
+                        Simultaneous => replay.players.len()
+                    };
+                    if actions.len() != expected {
+                        return error("wrong number of actions");
+                    }
+                    replay.events.push(Moves(actions));
+                },
+                ("eliminate", n) if n >= 2 => {
+                    let players: Vec<Option<PlayerIndex>> = words.slice_from(1).iter().map(|word| {
+                        from_str(*word).and_then(|p| if p < replay.players.len() { Some(p) } else { None })
+                    }).collect();
+                    if players.iter().any(|p| p.is_none()) {
+                        return error("invalid player index");
+                    }
+                    replay.events.push(Eliminated(players.move_iter().map(|p| p.unwrap()).collect()));
+                },
+                _ => return error("unrecognised line")
+            }
+        }
+        if !version_seen {
+            return Err("empty replay file".to_string());
+        }
+        if replay.board_width == 0 || replay.board_height == 0 || replay.players.len() < 2 {
+            return Err("replay is missing the board size or players".to_string());
+        }
+        let on_board = |&(row, column): &Position| row < replay.board_height && column < replay.board_width;
+        for (i, player) in replay.players.iter().enumerate() {
+            if !on_board(&player.position) ||
+                replay.players.slice_to(i).iter().any(|other| other.position == player.position) {
+                return Err(format!("player {} at {} is off the board or on another player", i, player.position));
+            }
+        }
+        // The rules may have been given after the turns.
+        let expected = match replay.rules {
+            Alternating => 1,
+            Simultaneous => replay.players.len()
+        };
+        if replay.events.iter().any(|event| match *event { Moves(ref actions) => actions.len() != expected, _ => false }) {
+            return Err("a turn has the wrong number of actions for the rules".to_string());
+        }
+        for (i, position) in replay.obstacles.iter().enumerate() {
+            if !on_board(position) {
+                return Err(format!("obstacle at {} is off the board", position));
+            }
+            if replay.obstacles.slice_to(i).contains(position) ||
+                replay.players.iter().any(|player| player.position == *position) {
+                return Err(format!("obstacle at {} is repeated or under a player", position));
+            }
+        }
+        Ok(replay)
+    }
+}
@@ -2,25 +2,58 @@
 #[phase(plugin, link)] extern crate log;
 extern crate ncurses;
 extern crate time;
+extern crate getopts;
 
 use game::{Direction, North, East, South, West, MoveForward};
-use game::{GameState, Player, Behaviour, PlayerTurn, PlayerHead, PlayerWall, Crash, Empty};
+use game::{Action, GameState, PlayerIndex, Bot, Behaviour, PlayerHead, PlayerWall, Crash, Obstacle, Empty};
+use driver::MatchSettings;
 use std::io::Timer;
 use std::time::Duration;
+use std::io::stdio;
 use std::io::stdio::print;
 use std::os;
 use std::comm::{channel, Receiver, Select};
+use std::rand::random;
+use std::cmp::{min, max};
+use replay::Replay;
+use time::precise_time_ns;
+use keys::KeyBindings;
+use config::{Config, BehaviourSpec, PlayMatch, WatchReplay, RunTournament, PlaySeries, ShowUsage};
 
 pub mod game;
 pub mod util;
+pub mod replay;
+pub mod config;
+pub mod driver;
+pub mod clock;
+pub mod tournament;
+pub mod zobrist;
+pub mod transposition;
+pub mod endgame;
+pub mod bitboard;
+pub mod map;
+pub mod start;
+pub mod series;
+pub mod keys;
 pub mod behaviour {
     pub mod static_action;
     pub mod stupid_random;
     pub mod minimax;
-//    pub mod minimax_memory;
+    pub mod external;
+    pub mod minimax_memory;
+    pub mod mcts;
 }
 
-static FRAME_DELAY_MS: i64 = 1000;
+static PLAYER_COLORS: [(i16, i16), ..8] = [
+    (ncurses::COLOR_RED, ncurses::COLOR_BLACK),
+    (ncurses::COLOR_CYAN, ncurses::COLOR_BLACK),
+    (ncurses::COLOR_GREEN, ncurses::COLOR_BLACK),
+    (ncurses::COLOR_YELLOW, ncurses::COLOR_BLACK),
+    (ncurses::COLOR_MAGENTA, ncurses::COLOR_BLACK),
+    (ncurses::COLOR_BLUE, ncurses::COLOR_BLACK),
+    (ncurses::COLOR_WHITE, ncurses::COLOR_BLACK),
+    (ncurses::COLOR_BLACK, ncurses::COLOR_WHITE)
+];
 
 fn direction_str(direction: Direction) -> &'static str {
     match direction {
@@ -31,29 +64,34 @@ fn direction_str(direction: Direction) -> &'static str {
     }
 }
 
-fn keyboard_controlled(direction_receiver: Receiver<Direction>) -> Behaviour {
-    Behaviour::make(proc(state_receiver, action_sender) {
-        loop {
-            let game = state_receiver.recv();
-            if game.is_over() {
-                debug!("Game is over, quitting.");
-                break;
-            };
-            
-            let mut timer = Timer::new().unwrap();
-            let timeout = timer.oneshot(Duration::milliseconds(FRAME_DELAY_MS - 20));
-            let action = select! {
-                direction = direction_receiver.recv() =>
-                    (game.players[game.current_player()]
-                     .direction.action_for(direction)
-                     .unwrap_or(MoveForward)),
-                () = timeout.recv() => MoveForward
-            };
+/// Steers with the directions typed by the player.
+struct KeyboardControlled {
+    direction_receiver: Receiver<Direction>
+}
 
-            debug!("Sending action {}", action);
-            action_sender.send((game.turn, action));
+impl Bot for KeyboardControlled {
+    fn act(&mut self, me: PlayerIndex, game: &GameState, deadline: u64) -> Action {
+        let now = precise_time_ns();
+        let remaining_ms = if deadline > now { ((deadline - now) / 1000000) as i64 } else { 0 };
+        let mut timer = Timer::new().unwrap();
+        let timeout = timer.oneshot(Duration::milliseconds(max(remaining_ms - 20, 0)));
+        let select = Select::new();
+        let mut direction_handle = select.handle(&self.direction_receiver);
+        let mut timeout_handle = select.handle(&timeout);
+        unsafe {
+            direction_handle.add();
+            timeout_handle.add();
+        }
+        if select.wait() == direction_handle.id() {
+            game.players[me].direction.action_for(direction_handle.recv()).unwrap_or(MoveForward)
+        } else {
+            MoveForward
         }
-    })
+    }
+}
+
+fn keyboard_controlled(direction_receiver: Receiver<Direction>) -> Behaviour {
+    Behaviour::from_bot(KeyboardControlled { direction_receiver: direction_receiver })
 }
 
 fn getch_each(f: |i32|) {
@@ -64,45 +102,7 @@ fn getch_each(f: |i32|) {
     }
 }
 
-fn key_direction(key: i32) -> Option<Direction> {
-    match key {
-        ncurses::KEY_UP => Some(North),
-        107 => Some(North), // k
-        ncurses::KEY_RIGHT => Some(East),
-        108 => Some(East), // l
-        ncurses::KEY_DOWN => Some(South),
-        106 => Some(South), // j
-        ncurses::KEY_LEFT => Some(West),
-        104 => Some(West), // h
-        _ => None
-    }
-}
-
-fn main() {
-    let mut game = GameState::new(40, 20, vec![
-        Player { name: "Player 1".to_string(), position: (10, 12), direction: North, is_alive: true },
-        Player { name: "Player 2".to_string(), position: (10, 28), direction: South, is_alive: true }
-    ]);
-
-    let all_args = os::args();
-    let options = all_args.slice(0, all_args.len());
-    let keyboard_control = options.iter().any(|x| *x == "-k".to_string() );
-
-    let (direction_sender, direction_receiver) = channel::<Direction>();
-
-    let behaviours = if keyboard_control {
-        vec![
-            keyboard_controlled(direction_receiver),
-            behaviour::minimax::minimax()
-        ]
-    } else {
-        vec![
-            behaviour::minimax::minimax(),
-            behaviour::minimax::minimax()
-        ]
-    };
-
-    // Curses init.
+fn init_curses() {
     ncurses::initscr();
     ncurses::raw();
     ncurses::keypad(ncurses::stdscr, true);
@@ -110,88 +110,320 @@ fn main() {
     ncurses::timeout(0);
     ncurses::curs_set(ncurses::CURSOR_INVISIBLE);
     ncurses::start_color();
-    ncurses::init_pair(1, ncurses::COLOR_RED, ncurses::COLOR_BLACK);
-    ncurses::init_pair(2, ncurses::COLOR_CYAN, ncurses::COLOR_BLACK);
+    for (i, &(foreground, background)) in PLAYER_COLORS.iter().enumerate() {
+        ncurses::init_pair(i as i16 + 1, foreground, background);
+    }
+}
 
+/// Draws the board in a frame whose edges show the topology: solid where
+/// driving off the board crashes, dotted where it wraps around.
+fn draw_game(game: &GameState) {
+    let side = if game.topology.wraps_horizontally() { ":" } else { "|" };
+    let top = if game.topology.wraps_vertically() { "~" } else { "-" };
+    let border = format!("+{}+\n", top.repeat(game.board_width));
+    ncurses::move(0, 0);
+    ncurses::printw(border.as_slice());
+    for row in game.board.iter() {
+        ncurses::printw(side);
+        for tile in row.iter() {
+            match *tile {
+                PlayerHead(p) => {
+                    ncurses::attron(ncurses::A_BOLD());
+                    ncurses::attron(ncurses::COLOR_PAIR(p as i16 + 1));
+                    ncurses::printw(direction_str(game.players[p].direction));
+                    ncurses::attroff(ncurses::COLOR_PAIR(p as i16 + 1));
+                    ncurses::attroff(ncurses::A_BOLD());
+                }
+                PlayerWall(x) => {
+                    ncurses::attron(ncurses::COLOR_PAIR(x as i16 + 1));
+                    ncurses::printw("#");
+                    ncurses::attroff(ncurses::COLOR_PAIR(x as i16 + 1));
+                }
+                Crash => { ncurses::printw("X"); }
+                Obstacle => {
+                    ncurses::attron(ncurses::A_REVERSE());
+                    ncurses::printw(" ");
+                    ncurses::attroff(ncurses::A_REVERSE());
+                }
+                Empty => { ncurses::printw("."); }
+            }
+        }
+        ncurses::printw(side);
+        ncurses::printw("\n");
+    }
+    ncurses::printw(border.as_slice());
+}
 
-    let mut quit = false;
-    while !game.status.is_over() && !quit {
-        getch_each(|key| {
-            if key == 113 { // q
-                quit = true;
+/// Steps through a recorded match. Space pauses, right/left (or l/h) step
+/// forward and back, +/- change the playback speed and q quits.
+fn play_replay(replay: &Replay, frame_delay_ms: i64) {
+    let states = replay.states();
+    let mut index = 0u;
+    let mut delay = frame_delay_ms as i32;
+    let mut paused = false;
+
+    init_curses();
+    loop {
+        let ref game = states[index];
+        draw_game(game);
+        ncurses::printw(format!("Turn: {}, status: {}\n", game.turn, game.status).as_slice());
+        ncurses::printw(format!("Replay {}/{}, {}, {} ms per turn\n", index, states.len() - 1,
+                                if paused { "paused" } else { "playing" }, delay).as_slice());
+        ncurses::clrtoeol();
+        ncurses::refresh();
+
+        ncurses::timeout(if paused { -1 } else { delay });
+        match ncurses::getch() {
+            113 => break, // q
+            32 => paused = !paused, // space
+            ncurses::KEY_RIGHT | 108 => { // l
+                paused = true;
+                index = min(index + 1, states.len() - 1);
             }
-            if keyboard_control {
-                key_direction(key).map(|dir| {
-                    direction_sender.send(dir);
-                });
+            ncurses::KEY_LEFT | 104 => { // h
+                paused = true;
+                if index > 0 {
+                    index -= 1;
+                }
             }
-        });
-
-        debug!("Turn {}, player {}", game.turn, game.current_player());
-
-        {
-            let ref behaviour = behaviours[game.current_player()];
-            behaviour.send_state(&game);
-            let mut timer = Timer::new().unwrap();
-            let timeout = timer.oneshot(Duration::milliseconds(FRAME_DELAY_MS));
-            let mut action = MoveForward;
-            let mut action_set = false;
-
-            let select = Select::new();
-            let mut timeout_handle  = select.handle(&timeout);
-            let mut behaviour_handle = select.handle(&behaviour.receiver);
-            unsafe {
-                timeout_handle.add();
-                behaviour_handle.add();
+            43 => delay = max(delay / 2, 10), // +
+            45 => delay = min(delay * 2, 10000), // -
+            ncurses::ERR if !paused => {
+                if index + 1 < states.len() {
+                    index += 1;
+                } else {
+                    paused = true;
+                }
             }
-            loop {
-                let id = select.wait();
-                if id == timeout_handle.id() {
-                    if !action_set {
-                        warn!("Turn {}, player {}: action was not set fast enough.", game.turn, game.current_player());
-                    }
-                    break;
-                } else if id == behaviour_handle.id() {
-                    let (turn, a) = behaviour.receiver.recv();
-                    if turn == game.turn {
-                        action = a;
-                        action_set = true;
-                    } else {
-                        warn!("Turn {}: received action for wrong turn {} from player {}.", game.turn, turn, game.current_player());
-                    };
-                };
+            _ => {}
+        }
+    }
+    ncurses::endwin();
+}
+
+fn save_replay(replay: &Replay, path: &str) {
+    match replay.save(&Path::new(path)) {
+        Ok(()) => info!("Replay saved to {}", path),
+        Err(e) => error!("Could not save replay to {}: {}", path, e)
+    }
+}
+
+/// Where the replay of game `number` of a series goes: `path` with the
+/// number added to the file name, as in `last-match-2.replay`.
+fn series_replay_path(path: &str, number: uint) -> String {
+    let path = Path::new(path);
+    let name = match (path.filestem_str(), path.extension_str()) {
+        (Some(stem), Some(extension)) => format!("{}-{}.{}", stem, number, extension),
+        (Some(stem), None) => format!("{}-{}", stem, number),
+        _ => format!("replay-{}", number)
+    };
+    path.with_filename(name).display().to_string()
+}
+
+/// Handles the keys pressed since the last turn, sending each keyboard
+/// player the directions of its own bindings, and redraws the board once
+/// every living player has moved, with `caption` below the status line.
+/// Returns `false` once q has been pressed.
+fn watch_turn(game: &GameState, round_over: bool, keyboards: &[(KeyBindings, Sender<Direction>)],
+              caption: &str) -> bool {
+    let mut quit = false;
+    getch_each(|key| {
+        if key == 113 { // q
+            quit = true;
+        }
+        for &(ref bindings, ref sender) in keyboards.iter() {
+            bindings.direction(key).map(|dir| {
+                sender.send(dir);
+            });
+        }
+    });
+
+    // Redraw once every living player has moved.
+    if round_over {
+        draw_game(game);
+        ncurses::printw(format!("Turn: {}, status: {}\n", game.turn, game.status).as_slice());
+        ncurses::printw(caption);
+        ncurses::clrtoeol();
+        ncurses::refresh();
+    }
+    !quit
+}
+
+/// Builds the behaviour for `spec`; keyboard players take the next of
+/// `direction_receivers`.
+fn make_behaviour(spec: &BehaviourSpec, config: &Config, seed: u64,
+                  direction_receivers: &mut Vec<Receiver<Direction>>) -> Behaviour {
+    match *spec {
+        config::Minimax(heuristic) => {
+            let evaluation: util::Evaluation = match heuristic {
+                config::Space => behaviour::minimax::space_evaluation,
+                config::Voronoi => util::voronoi_evaluation
             };
-            game.do_turn(action);
-        };
-
-        if game.status.is_over() || game.status == PlayerTurn(0) {
-            ncurses::move(0, 0);
-            for row in game.board.iter() {
-                for tile in row.iter() {
-                    match *tile {
-                        PlayerHead(p) => {
-                            ncurses::attron(ncurses::A_BOLD());
-                            ncurses::attron(ncurses::COLOR_PAIR(p as i16 + 1));
-                            ncurses::printw(direction_str(game.players[p].direction));
-                            ncurses::attroff(ncurses::COLOR_PAIR(p as i16 + 1));
-                            ncurses::attroff(ncurses::A_BOLD());
-                        }
-                        PlayerWall(x) => {
-                            ncurses::attron(ncurses::COLOR_PAIR(x as i16 + 1));
-                            ncurses::printw("#");
-                            ncurses::attroff(ncurses::COLOR_PAIR(x as i16 + 1));
-                        }
-                        Crash => { ncurses::printw("X"); }
-                        Empty => { ncurses::printw("."); }
-                    }
+            behaviour::minimax::minimax(evaluation)
+        }
+        config::MinimaxMemory(heuristic) => {
+            let evaluation: util::Evaluation = match heuristic {
+                config::Space => behaviour::minimax_memory::space_evaluation,
+                config::Voronoi => util::voronoi_evaluation
+            };
+            behaviour::minimax_memory::minimax_memory(seed, evaluation)
+        }
+        config::Mcts => behaviour::mcts::mcts(seed, config.ponder),
+        config::StupidRandom(stability) => behaviour::stupid_random::stupid_random(stability, seed),
+        config::StaticAction(action) => behaviour::static_action::static_action(action),
+        config::Keyboard => keyboard_controlled(direction_receivers.remove(0).unwrap()),
+        config::External(ref program, ref args) => {
+            behaviour::external::external(program.clone(), args.clone(), seed)
+        }
+    }
+}
+
+/// Plays a series, in the terminal with the running score under the board
+/// or headless with a summary for scripts. Each game's replay is saved next
+/// to the configured replay path, numbered.
+fn play_series(config: &Config, format: &series::SeriesFormat, behaviours: Vec<Behaviour>, seed: u64,
+               keyboards: &[(KeyBindings, Sender<Direction>)]) {
+    if config.headless {
+        let result = series::play(config, format, behaviours, seed, |game, behaviours, settings, game_seed, so_far| {
+            let outcome = driver::play_match(game, behaviours, settings, game_seed, |_, _| true);
+            save_replay(&outcome.replay, series_replay_path(config.replay_path.as_slice(),
+                                                            so_far.games.len() + 1).as_slice());
+            outcome
+        });
+        match result.write_summary(&mut stdio::stdout()) {
+            Ok(()) => {}
+            Err(e) => error!("Could not write the result: {}", e)
+        }
+        return;
+    }
+
+    init_curses();
+    let result = series::play(config, format, behaviours, seed, |game, behaviours, settings, game_seed, so_far| {
+        let score = so_far.score_line();
+        ncurses::clear();
+        let outcome = driver::play_match(game, behaviours, settings, game_seed, |game, round_over| {
+            watch_turn(game, round_over, keyboards, score.as_slice())
+        });
+        save_replay(&outcome.replay, series_replay_path(config.replay_path.as_slice(),
+                                                        so_far.games.len() + 1).as_slice());
+        if !outcome.aborted {
+            let game = &outcome.game;
+            let winners: Vec<&str> = outcome.winners().iter().map(|&p| game.players[p].name.as_slice()).collect();
+            let verdict = if winners.len() == 1 { format!("{} wins", winners[0]) } else { "Draw".to_string() };
+            ncurses::printw(format!("\n{}. Press any key to go on.", verdict).as_slice());
+            ncurses::refresh();
+            ncurses::timeout(-1);
+            ncurses::getch();
+            ncurses::timeout(0);
+        }
+        outcome
+    });
+    ncurses::endwin();
+
+    let winners: Vec<&str> = result.winners().iter().map(|&e| result.names[e].as_slice()).collect();
+    let verdict = if result.aborted {
+        "aborted".to_string()
+    } else if winners.len() == 1 {
+        format!("won by {}", winners[0])
+    } else {
+        format!("drawn between {}", winners.connect(", "))
+    };
+    print(format!("Series {}, {} after {} games: {}\n", format.describe(), verdict,
+                  result.games.len(), result.scores()).as_slice());
+}
+
+fn main() {
+    let (config, series_format) = match config::parse_args(os::args().as_slice()) {
+        Ok(PlayMatch(config)) => (config, None),
+        Ok(PlaySeries(config, format)) => (config, Some(format)),
+        Ok(WatchReplay(path, frame_delay_ms)) => {
+            match Replay::load(&Path::new(path.as_slice())) {
+                Ok(replay) => play_replay(&replay, frame_delay_ms),
+                Err(e) => {
+                    print(format!("Could not load replay: {}\n", e).as_slice());
+                    os::set_exit_status(1);
                 }
-                ncurses::printw("\n");
             }
-            ncurses::printw(format!("Turn: {}, status: {}\n", game.turn, game.status).as_slice());
-            ncurses::refresh();
+            return;
+        }
+        Ok(RunTournament(config, settings)) => {
+            let mut no_keyboard = vec![];
+            let result = tournament::run(&config, &settings, |spec, seed| {
+                make_behaviour(spec, &config, seed, &mut no_keyboard)
+            });
+            match result.write_report(&mut stdio::stdout()) {
+                Ok(()) => {}
+                Err(e) => error!("Could not write the tournament report: {}", e)
+            }
+            return;
+        }
+        Ok(ShowUsage(usage)) => {
+            print(usage.as_slice());
+            return;
+        }
+        Err(e) => {
+            print(format!("{}\nTry --help for usage.\n", e).as_slice());
+            os::set_exit_status(2);
+            return;
+        }
+    };
+
+    let player_count = config.behaviours.len();
+    let seed = match config.seed {
+        Some(seed) => seed,
+        None => random()
+    };
+    // Each keyboard player listens on its own channel for its own keys.
+    let mut keyboards = vec![];
+    let mut direction_receivers = vec![];
+    for bindings in config.key_bindings.iter() {
+        let (direction_sender, direction_receiver) = channel::<Direction>();
+        keyboards.push((bindings.clone(), direction_sender));
+        direction_receivers.push(direction_receiver);
+    }
+    let behaviours: Vec<Behaviour> = config.behaviours.iter().enumerate().map(|(i, spec)| {
+        make_behaviour(spec, &config, util::mix_seed(seed, i as u64), &mut direction_receivers)
+    }).collect();
+
+    match series_format {
+        Some(format) => {
+            play_series(&config, &format, behaviours, seed, keyboards.as_slice());
+            return;
         }
+        None => {}
     }
 
+    let game = match start::opening(&config, player_count, seed, 0.0) {
+        Ok(game) => game,
+        Err(e) => {
+            print(format!("{}\n", e).as_slice());
+            os::set_exit_status(1);
+            return;
+        }
+    };
+    let settings = MatchSettings {
+        time_controls: config.time_controls.clone(),
+        late_policy: config.late_policy.clone(),
+        frame_delay_ms: if config.headless { None } else { Some(config.frame_delay_ms) }
+    };
+
+    if config.headless {
+        let result = driver::play_match(game, behaviours.as_slice(), &settings, seed, |_, _| true);
+        save_replay(&result.replay, config.replay_path.as_slice());
+        match result.write_summary(&mut stdio::stdout()) {
+            Ok(()) => {}
+            Err(e) => error!("Could not write the result: {}", e)
+        }
+        return;
+    }
+
+    init_curses();
+
+    let result = driver::play_match(game, behaviours.as_slice(), &settings, seed, |game, round_over| {
+        watch_turn(game, round_over, keyboards.as_slice(), "")
+    });
+    let game = &result.game;
+
     ncurses::timeout(-1);
     ncurses::printw("Press any key to exit.");
     ncurses::getch();
@@ -199,4 +431,13 @@ fn main() {
     ncurses::endwin();
 
     print(format!("Turn: {}, status: {}\n", game.turn, game.status).as_slice());
+    save_replay(&result.replay, config.replay_path.as_slice());
+    for (place, players) in result.ranking().iter().enumerate() {
+        let names: Vec<&str> = players.iter().map(|&p| game.players[p].name.as_slice()).collect();
+        print(format!("{}. {}\n", place + 1, names.connect(", ")).as_slice());
+    }
+    for failure in result.failures.iter() {
+        print(format!("{} failed on turn {}: {}\n", game.players[failure.player].name,
+                      failure.turn, failure.reason).as_slice());
+    }
 }
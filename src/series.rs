@@ -0,0 +1,169 @@
+//! Several games in a row between the same players.
+//!
+//! The behaviours play every game of the series, being told about each new
+//! game by its first state. Players move one seat on after every game so
+//! that nobody keeps the first move or the best start, and each game won
+//! outright scores a point; draws score nothing.
+
+use config::Config;
+use driver::{MatchSettings, MatchResult, index_list};
+use game::{Behaviour, GameState};
+use start;
+use std::io::IoResult;
+
+#[deriving(Clone, Show, PartialEq)]
+pub enum SeriesFormat {
+    BestOf(uint), // Games to play at most; stops once the leader cannot be caught.
+    FirstTo(uint) // Wins needed.
+}
+
+/// Games a first-to series may last per win needed, so that a run of draws
+/// cannot go on forever.
+static MAX_GAMES_PER_WIN: uint = 5;
+
+impl SeriesFormat {
+    pub fn describe(&self) -> String {
+        match *self {
+            BestOf(games) => format!("best of {}", games),
+            FirstTo(wins) => format!("first to {}", wins)
+        }
+    }
+}
+
+pub struct GameRecord {
+    pub seats: Vec<uint>, // Entrant in each seat.
+    pub winners: Vec<uint>, // Entrants sharing first place.
+    pub outcome: MatchResult // Indexed by seat.
+}
+
+pub struct SeriesResult {
+    pub format: SeriesFormat,
+    pub names: Vec<String>, // Of the entrants, in the order of the first game's seats.
+    pub games: Vec<GameRecord>,
+    pub wins: Vec<uint>, // Games each entrant won outright.
+    pub aborted: bool
+}
+
+impl SeriesResult {
+    fn best_wins(&self) -> uint {
+        self.wins.iter().map(|&w| w).max().unwrap_or(0)
+    }
+
+    pub fn is_over(&self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        let played = self.games.len();
+        match self.format {
+            BestOf(games) => {
+                let mut wins = self.wins.clone();
+                wins.sort_by(|a, b| b.cmp(a));
+                played >= games || wins[0] > wins[1] + (games - played)
+            }
+            FirstTo(wins) => self.best_wins() >= wins || played >= wins * MAX_GAMES_PER_WIN
+        }
+    }
+
+    /// Entrants with the most wins once the series is over; everyone ties
+    /// while nobody has won a game.
+    pub fn winners(&self) -> Vec<uint> {
+        if !self.is_over() {
+            return vec![];
+        }
+        let best = self.best_wins();
+        range(0, self.wins.len()).filter(|&e| self.wins[e] == best).collect()
+    }
+
+    /// The entrants' names and wins, as in `Player 1 2, Player 2 1`.
+    pub fn scores(&self) -> String {
+        let scores: Vec<String> = self.names.iter().zip(self.wins.iter()).map(|(name, wins)| {
+            format!("{} {}", name, wins)
+        }).collect();
+        scores.connect(", ")
+    }
+
+    /// The game about to be played and the scores so far, on one line.
+    pub fn score_line(&self) -> String {
+        format!("Game {}, {}: {}", self.games.len() + 1, self.format.describe(), self.scores())
+    }
+
+    /// Writes every game's summary after a `game <number> seats <entrants>`
+    /// line, then the series outcome, each entrant's wins and the series
+    /// winners. Entrants are numbered by their seat in the first game.
+    pub fn write_summary(&self, writer: &mut Writer) -> IoResult<()> {
+        for (i, game) in self.games.iter().enumerate() {
+            try!(writer.write_str(format!("game {} seats {}\n", i + 1, index_list(game.seats.as_slice())).as_slice()));
+            try!(game.outcome.write_summary(writer));
+        }
+        let outcome = if self.aborted {
+            "aborted"
+        } else if self.winners().len() == 1 {
+            "won"
+        } else {
+            "draw"
+        };
+        try!(writer.write_str(format!("series {}\n", outcome).as_slice()));
+        for (entrant, wins) in self.wins.iter().enumerate() {
+            try!(writer.write_str(format!("wins {} {}\n", entrant, wins).as_slice()));
+        }
+        try!(writer.write_str(format!("winners {}\n", index_list(self.winners().as_slice())).as_slice()));
+        Ok(())
+    }
+}
+
+/// Plays a series between `behaviours`, one per player of `config`, each
+/// game starting from `start::opening` with its own seed derived from
+/// `seed`. `play_game` plays a game given its opening, the behaviours in
+/// seat order, the settings, the game's seed and the series so far.
+pub fn play(config: &Config, format: &SeriesFormat, mut behaviours: Vec<Behaviour>, seed: u64,
+            play_game: |GameState, &[Behaviour], &MatchSettings, u64, &SeriesResult| -> MatchResult) -> SeriesResult {
+    let n = behaviours.len();
+    let mut seats: Vec<uint> = range(0, n).collect();
+    let mut result = SeriesResult {
+        format: format.clone(),
+        names: range(0, n).map(|e| format!("Player {}", e + 1)).collect(),
+        games: vec![],
+        wins: Vec::from_elem(n, 0u),
+        aborted: false
+    };
+
+    while !result.is_over() {
+        let game_seed = seed + result.games.len() as u64;
+        let mut game = match start::opening(config, n, game_seed, 0.0) {
+            Ok(game) => game,
+            Err(e) => {
+                error!("Could not set up game {} of the series: {}", result.games.len() + 1, e);
+                result.aborted = true;
+                break;
+            }
+        };
+        for (seat, &entrant) in seats.iter().enumerate() {
+            game.players.get_mut(seat).name = result.names[entrant].clone();
+        }
+        let settings = MatchSettings {
+            time_controls: seats.iter().map(|&e| config.time_controls[e].clone()).collect(),
+            late_policy: config.late_policy.clone(),
+            frame_delay_ms: if config.headless { None } else { Some(config.frame_delay_ms) }
+        };
+
+        let outcome = play_game(game, behaviours.as_slice(), &settings, game_seed, &result);
+        let winners: Vec<uint> = outcome.winners().iter().map(|&seat| seats[seat]).collect();
+        if winners.len() == 1 {
+            *result.wins.get_mut(winners[0]) += 1;
+        }
+        info!("Game {}: winners {}", result.games.len() + 1, winners);
+        result.aborted = outcome.aborted;
+        result.games.push(GameRecord {
+            seats: seats.clone(),
+            winners: winners,
+            outcome: outcome
+        });
+
+        // Everyone moves one seat on.
+        let behaviour = behaviours.remove(0).unwrap();
+        behaviours.push(behaviour);
+        let entrant = seats.remove(0).unwrap();
+        seats.push(entrant);
+    }
+    result
+}
@@ -0,0 +1,191 @@
+//! Random fair starting positions.
+//!
+//! One player's start is picked at random and the others are its images
+//! under symmetries of the board, so that every player faces the same
+//! situation: two players start point-symmetric, four start rotated by
+//! quarter turns on square boards and mirrored on others, and eight use
+//! both on square boards. Other player counts are spread around an ellipse
+//! turned by a random angle, which is as even as the grid allows. Picks are
+//! retried until every player starts on a free cell facing a free cell, far
+//! enough from the others and able to reach them.
+
+use game::{Direction, North, East, South, West, GameState, Player, Position, Topology};
+use game::players_on_ellipse;
+use config::Config;
+use util::seeded_rng;
+use std::rand::Rng;
+use std::f64;
+
+/// Random picks tried before giving up.
+static ATTEMPTS: uint = 1000;
+
+static DIRECTIONS: [Direction, ..4] = [North, East, South, West];
+
+fn mirror_columns(direction: Direction) -> Direction {
+    match direction {
+        East => West,
+        West => East,
+        other => other
+    }
+}
+
+fn mirror_rows(direction: Direction) -> Direction {
+    match direction {
+        North => South,
+        South => North,
+        other => other
+    }
+}
+
+/// `start` and its quarter turns around the centre of a square board.
+fn rotations(start: (Position, Direction), size: uint) -> Vec<(Position, Direction)> {
+    let mut images = vec![start];
+    for _ in range(1u, 4) {
+        let ((r, c), direction) = *images.last().unwrap();
+        images.push(((c, size - 1 - r), direction.right()));
+    }
+    images
+}
+
+/// The starts of `count` players given the first one's, or `None` if the
+/// board has no symmetry for that many players.
+fn symmetric_starts(start: (Position, Direction), count: uint, width: uint, height: uint) -> Option<Vec<(Position, Direction)>> {
+    let ((r, c), direction) = start;
+    let (last_r, last_c) = (height - 1, width - 1);
+    match count {
+        2 => Some(vec![start, ((last_r - r, last_c - c), direction.right().right())]),
+        4 if width == height => Some(rotations(start, width)),
+        4 => Some(vec![
+            start,
+            ((r, last_c - c), mirror_columns(direction)),
+            ((last_r - r, last_c - c), direction.right().right()),
+            ((last_r - r, c), mirror_rows(direction))
+        ]),
+        8 if width == height => {
+            let mut starts = rotations(start, width);
+            starts.push_all(rotations(((r, last_c - c), mirror_columns(direction)), width).as_slice());
+            Some(starts)
+        }
+        _ => None
+    }
+}
+
+fn distance(a: Position, b: Position) -> uint {
+    let ((ar, ac), (br, bc)) = (a, b);
+    let rows = if ar > br { ar - br } else { br - ar };
+    let columns = if ac > bc { ac - bc } else { bc - ac };
+    rows + columns
+}
+
+/// Whether `starts` are on free, distinct cells, each facing a free cell,
+/// at least `min_distance` apart and all in one region.
+fn is_fair(starts: &[(Position, Direction)], width: uint, height: uint, blocked: &[bool],
+           topology: &Topology, min_distance: uint) -> bool {
+    let free = |position: Position| {
+        let (r, c) = position;
+        r < height && c < width && !blocked[r * width + c] &&
+            !starts.iter().any(|&(other, _)| other == position)
+    };
+    for (i, &(position, direction)) in starts.iter().enumerate() {
+        let (r, c) = position;
+        if r >= height || c >= width || blocked[r * width + c] {
+            return false;
+        }
+        if !topology.step(position, direction, width, height).map_or(false, |ahead| free(ahead)) {
+            return false;
+        }
+        if starts.slice_to(i).iter().any(|&(other, _)| distance(position, other) < min_distance) {
+            return false;
+        }
+    }
+
+    // Every player must be able to reach the others.
+    let (first, _) = starts[0];
+    let mut seen = Vec::from_elem(width * height, false);
+    let mut stack = vec![first];
+    let (r, c) = first;
+    *seen.get_mut(r * width + c) = true;
+    loop {
+        let position = match stack.pop() {
+            Some(position) => position,
+            None => break
+        };
+        for &direction in DIRECTIONS.iter() {
+            match topology.step(position, direction, width, height) {
+                Some((nr, nc)) if !blocked[nr * width + nc] && !seen[nr * width + nc] => {
+                    *seen.get_mut(nr * width + nc) = true;
+                    stack.push((nr, nc));
+                }
+                _ => {}
+            }
+        }
+    }
+    starts.iter().all(|&((r, c), _)| seen[r * width + c])
+}
+
+/// Fair starts for `count` players on a `width` by `height` board with
+/// `obstacles`, chosen from `seed`.
+pub fn fair_players(width: uint, height: uint, obstacles: &[Position], topology: &Topology,
+                    count: uint, seed: u64) -> Result<Vec<Player>, String> {
+    let mut blocked = Vec::from_elem(width * height, false);
+    for &(r, c) in obstacles.iter() {
+        *blocked.get_mut(r * width + c) = true;
+    }
+    let min_distance = if width < height { width / 4 } else { height / 4 } + 2;
+    let mut rng = seeded_rng(seed);
+    for _ in range(0, ATTEMPTS) {
+        let first = ((rng.gen_range(0, height), rng.gen_range(0, width)), DIRECTIONS[rng.gen_range(0u, 4)]);
+        let starts = match symmetric_starts(first, count, width, height) {
+            Some(starts) => starts,
+            None => {
+                let rotation = rng.gen_range(0.0, 2.0 * f64::consts::PI);
+                players_on_ellipse(count, width, height, rotation).iter()
+                    .map(|player| (player.position, player.direction)).collect()
+            }
+        };
+        if is_fair(starts.as_slice(), width, height, blocked.as_slice(), topology, min_distance) {
+            return Ok(starts.iter().enumerate().map(|(i, &(position, direction))| {
+                Player {
+                    name: format!("Player {}", i + 1),
+                    position: position,
+                    direction: direction,
+                    is_alive: true
+                }
+            }).collect());
+        }
+    }
+    Err(format!("found no fair start for {} players in {} tries", count, ATTEMPTS))
+}
+
+/// The opening position of a game for `count` players under `config`. The
+/// players start at fair positions chosen by `seed` with `--random-starts`,
+/// at the map's start cells on a map, and otherwise on the ellipse turned by
+/// `rotation`. Fails if two players would start on the same cell or on an
+/// obstacle.
+pub fn opening(config: &Config, count: uint, seed: u64, rotation: f64) -> Result<GameState, String> {
+    let players = if config.random_starts {
+        let obstacles = config.map.as_ref().map_or(vec![], |map| map.obstacles.clone());
+        try!(fair_players(config.board_width, config.board_height, obstacles.as_slice(), &config.topology,
+                          count, seed))
+    } else {
+        match config.map {
+            Some(ref map) => map.players(count),
+            None => players_on_ellipse(count, config.board_width, config.board_height, rotation)
+        }
+    };
+    // Small boards squeeze the ellipse until players share cells.
+    let obstacles = config.map.as_ref().map_or(vec![], |map| map.obstacles.clone());
+    for (i, player) in players.iter().enumerate() {
+        if players.slice_to(i).iter().any(|other| other.position == player.position) ||
+            obstacles.contains(&player.position) {
+            return Err(format!("the board is too small for {} players to start on separate free cells", count));
+        }
+    }
+    let mut game = match config.map {
+        Some(ref map) => map.new_game(players, config.rules.clone()),
+        None => GameState::new(config.board_width, config.board_height, players, config.rules.clone())
+    };
+    game.turn_limit = config.turn_limit;
+    game.topology = config.topology.clone();
+    Ok(game)
+}
@@ -0,0 +1,289 @@
+//! Series of two-player games between many behaviours.
+//!
+//! Every pairing plays the same openings from both seats, so that any
+//! first-move or position advantage cancels out, and the results are
+//! summarised as win/draw/loss tables and Elo ratings.
+
+use config::{Config, BehaviourSpec};
+use driver;
+use driver::MatchSettings;
+use game::Behaviour;
+use start;
+use util::mix_seed;
+use std::io::IoResult;
+use std::f64;
+
+#[deriving(Clone, Show, PartialEq)]
+pub enum Format {
+    RoundRobin,
+    Swiss(uint) // Number of rounds.
+}
+
+#[deriving(Clone, Show)]
+pub struct TournamentSettings {
+    pub format: Format,
+    pub games_per_pairing: uint // Openings per pairing, each played from both seats.
+}
+
+#[deriving(Clone, Show)]
+pub struct GameRecord {
+    pub first: uint, // Entrant in the first seat.
+    pub second: uint,
+    pub score: f64, // For the first seat: 1 for a win, 0.5 for a draw, 0 for a loss.
+    pub seed: u64
+}
+
+pub struct TournamentResult {
+    pub names: Vec<String>,
+    pub games: Vec<GameRecord>
+}
+
+/// Rating given to an entrant with an even record.
+static BASE_RATING: f64 = 1500.0;
+
+fn expected_score(rating_difference: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-rating_difference / 400.0))
+}
+
+impl TournamentResult {
+    fn score_for(&self, entrant: uint, game: &GameRecord) -> Option<f64> {
+        if game.first == entrant {
+            Some(game.score)
+        } else if game.second == entrant {
+            Some(1.0 - game.score)
+        } else {
+            None
+        }
+    }
+
+    /// Wins, draws and losses of `entrant`, optionally only against `opponent`.
+    pub fn record(&self, entrant: uint, opponent: Option<uint>) -> (uint, uint, uint) {
+        let mut record = (0u, 0u, 0u);
+        for game in self.games.iter() {
+            let against = if game.first == entrant { game.second } else { game.first };
+            if opponent.map_or(false, |o| o != against) {
+                continue;
+            }
+            let (ref mut wins, ref mut draws, ref mut losses) = record;
+            match self.score_for(entrant, game) {
+                Some(s) if s > 0.5 => *wins += 1,
+                Some(s) if s < 0.5 => *losses += 1,
+                Some(_) => *draws += 1,
+                None => {}
+            }
+        }
+        record
+    }
+
+    pub fn points(&self, entrant: uint) -> f64 {
+        self.games.iter().filter_map(|game| self.score_for(entrant, game)).fold(0.0, |a, b| a + b)
+    }
+
+    /// Maximum likelihood Elo ratings and the half-width of their 95%
+    /// confidence intervals. Every entrant is credited with one virtual draw
+    /// against an average opponent, which keeps ratings finite for perfect
+    /// records.
+    pub fn ratings(&self) -> Vec<(f64, f64)> {
+        let n = self.names.len();
+        let k = f64::consts::LN_10 / 400.0;
+        let mut ratings = Vec::from_elem(n, 0.0f64);
+        let mut information = Vec::from_elem(n, 0.0f64);
+        for _ in range(0u, 100) {
+            let mut gradient = Vec::from_elem(n, 0.0f64);
+            information = Vec::from_elem(n, 0.0f64);
+            for i in range(0, n) {
+                let e = expected_score(ratings[i]);
+                *gradient.get_mut(i) += k * (0.5 - e);
+                *information.get_mut(i) += k * k * e * (1.0 - e);
+            }
+            for game in self.games.iter() {
+                let (a, b) = (game.first, game.second);
+                let e = expected_score(ratings[a] - ratings[b]);
+                *gradient.get_mut(a) += k * (game.score - e);
+                *gradient.get_mut(b) -= k * (game.score - e);
+                *information.get_mut(a) += k * k * e * (1.0 - e);
+                *information.get_mut(b) += k * k * e * (1.0 - e);
+            }
+            for i in range(0, n) {
+                *ratings.get_mut(i) += gradient[i] / information[i];
+            }
+        }
+        let mean = ratings.iter().fold(0.0, |a, &b| a + b) / (n as f64);
+        range(0, n).map(|i| {
+            (BASE_RATING + ratings[i] - mean, 1.96 / information[i].sqrt())
+        }).collect()
+    }
+
+    pub fn write_report(&self, writer: &mut Writer) -> IoResult<()> {
+        let ratings = self.ratings();
+        let mut order: Vec<uint> = range(0, self.names.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (ra, _) = ratings[a];
+            let (rb, _) = ratings[b];
+            rb.partial_cmp(&ra).unwrap()
+        });
+
+        try!(writer.write_line(format!("{:>4s} {:<24s} {:>5s} {:>4s} {:>4s} {:>4s} {:>6s} {:>6s} {:>5s}",
+                                       "Rank", "Entrant", "Games", "W", "D", "L", "Points", "Elo", "95%").as_slice()));
+        for (rank, &i) in order.iter().enumerate() {
+            let (wins, draws, losses) = self.record(i, None);
+            let (rating, interval) = ratings[i];
+            try!(writer.write_line(format!("{:>4u} {:<24s} {:>5u} {:>4u} {:>4u} {:>4u} {:>6.1f} {:>6.0f} {:>5.0f}",
+                                           rank + 1, self.names[i], wins + draws + losses, wins, draws, losses,
+                                           self.points(i), rating, interval).as_slice()));
+        }
+
+        try!(writer.write_line(""));
+        try!(writer.write_line("Wins-draws-losses of each row against each column:"));
+        let mut header = format!("{:<24s}", "");
+        for &j in order.iter() {
+            header.push_str(format!(" {:>9s}", format!("#{}", j + 1)).as_slice());
+        }
+        try!(writer.write_line(header.as_slice()));
+        for &i in order.iter() {
+            let mut line = format!("{:<24s}", format!("#{} {}", i + 1, self.names[i]));
+            for &j in order.iter() {
+                let cell = if i == j {
+                    "-".to_string()
+                } else {
+                    let (wins, draws, losses) = self.record(i, Some(j));
+                    format!("{}-{}-{}", wins, draws, losses)
+                };
+                line.push_str(format!(" {:>9s}", cell).as_slice());
+            }
+            try!(writer.write_line(line.as_slice()));
+        }
+        Ok(())
+    }
+}
+
+struct Tournament<'a> {
+    config: &'a Config,
+    settings: &'a TournamentSettings,
+    make_behaviour: |&BehaviourSpec, u64|: 'a -> Behaviour,
+    result: TournamentResult,
+    opening: uint
+}
+
+impl<'a> Tournament<'a> {
+    fn play_game(&mut self, first: uint, second: uint, rotation: f64, seed: u64) {
+        // Maps have fixed start cells, so unless starts are random their
+        // openings differ only in the seed.
+        let mut game = match start::opening(self.config, 2, seed, rotation) {
+            Ok(game) => game,
+            Err(e) => {
+                error!("Skipping the opening with seed {}: {}", seed, e);
+                return;
+            }
+        };
+        game.players.get_mut(0).name = self.result.names[first].clone();
+        game.players.get_mut(1).name = self.result.names[second].clone();
+        let behaviours = vec![
+            (self.make_behaviour)(&self.config.behaviours[first], mix_seed(seed, 0)),
+            (self.make_behaviour)(&self.config.behaviours[second], mix_seed(seed, 1))
+        ];
+        let settings = MatchSettings {
+            time_controls: vec![self.config.time_controls[first].clone(), self.config.time_controls[second].clone()],
+            late_policy: self.config.late_policy.clone(),
+            frame_delay_ms: None
+        };
+        let outcome = driver::play_match(game, behaviours.as_slice(), &settings, seed, |_, _| true);
+        let winners = outcome.winners();
+        let score = if winners.len() != 1 {
+            0.5
+        } else if winners[0] == 0 {
+            1.0
+        } else {
+            0.0
+        };
+        info!("{} vs {} (seed {}): {}", self.result.names[first], self.result.names[second], seed, score);
+        self.result.games.push(GameRecord {
+            first: first,
+            second: second,
+            score: score,
+            seed: seed
+        });
+    }
+
+    /// Plays every opening of a pairing from both seats.
+    fn play_pairing(&mut self, a: uint, b: uint) {
+        for _ in range(0, self.settings.games_per_pairing) {
+            // Successive openings are spread evenly around the board by
+            // turning them by the golden angle.
+            let rotation = (self.opening as f64) * f64::consts::PI * (3.0 - 5f64.sqrt());
+            let seed = self.config.seed.unwrap_or(0) + self.opening as u64;
+            self.opening += 1;
+            self.play_game(a, b, rotation, seed);
+            self.play_game(b, a, rotation, seed);
+        }
+    }
+
+    fn has_played(&self, a: uint, b: uint) -> bool {
+        self.result.games.iter().any(|game| {
+            (game.first == a && game.second == b) || (game.first == b && game.second == a)
+        })
+    }
+
+    /// Pairs entrants with similar points that have not met yet. With an odd
+    /// number of entrants the lowest ranked unpaired one sits the round out.
+    fn swiss_round(&mut self) {
+        let n = self.result.names.len();
+        let points: Vec<f64> = range(0, n).map(|i| self.result.points(i)).collect();
+        let mut order: Vec<uint> = range(0, n).collect();
+        order.sort_by(|&a, &b| points[b].partial_cmp(&points[a]).unwrap());
+        let mut paired = Vec::from_elem(n, false);
+        let mut pairings = vec![];
+        for (k, &a) in order.iter().enumerate() {
+            if paired[a] {
+                continue;
+            }
+            let candidates: Vec<uint> = order.slice_from(k + 1).iter().map(|&b| b).filter(|&b| !paired[b]).collect();
+            let opponent = candidates.iter().find(|&&b| !self.has_played(a, b)).or(candidates.iter().next());
+            match opponent {
+                Some(&b) => {
+                    *paired.get_mut(a) = true;
+                    *paired.get_mut(b) = true;
+                    pairings.push((a, b));
+                }
+                None => info!("{} has a bye", self.result.names[a])
+            }
+        }
+        for &(a, b) in pairings.iter() {
+            self.play_pairing(a, b);
+        }
+    }
+}
+
+/// Plays a tournament between the behaviours of `config`, creating fresh
+/// behaviours for every game with `make_behaviour`, which is passed the
+/// seed for the behaviour's random choices.
+pub fn run(config: &Config, settings: &TournamentSettings,
+           make_behaviour: |&BehaviourSpec, u64| -> Behaviour) -> TournamentResult {
+    let mut tournament = Tournament {
+        config: config,
+        settings: settings,
+        make_behaviour: make_behaviour,
+        result: TournamentResult {
+            names: config.behaviours.iter().map(|spec| spec.describe()).collect(),
+            games: vec![]
+        },
+        opening: 0
+    };
+    let n = config.behaviours.len();
+    match settings.format {
+        RoundRobin => {
+            for a in range(0, n) {
+                for b in range(a + 1, n) {
+                    tournament.play_pairing(a, b);
+                }
+            }
+        }
+        Swiss(rounds) => {
+            for round in range(0, rounds) {
+                info!("Swiss round {}", round + 1);
+                tournament.swiss_round();
+            }
+        }
+    }
+    tournament.result
+}
@@ -0,0 +1,75 @@
+//! Bounded cache of search results keyed by `GameState::hash`.
+
+use game::Action;
+
+#[deriving(PartialEq, Eq, Show, Clone)]
+pub enum Bound {
+    Exact,
+    LowerBound, // The real score is at least the stored one.
+    UpperBound // The real score is at most the stored one.
+}
+
+#[deriving(Show, Clone)]
+pub struct Entry {
+    pub hash: u64,
+    pub depth: uint, // Remaining search depth the score was computed with.
+    pub score: f64,
+    pub bound: Bound,
+    pub best_action: Option<Action>
+}
+
+/// Fixed-size table with one entry per slot. A new entry replaces the old
+/// one unless the old one describes the same position searched deeper.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    pub hits: uint,
+    pub misses: uint
+}
+
+impl TranspositionTable {
+    pub fn new(capacity: uint) -> TranspositionTable {
+        assert!(capacity > 0);
+        TranspositionTable {
+            entries: Vec::from_elem(capacity, None),
+            hits: 0,
+            misses: 0
+        }
+    }
+
+    fn slot(&self, hash: u64) -> uint {
+        (hash % self.entries.len() as u64) as uint
+    }
+
+    pub fn probe(&mut self, hash: u64) -> Option<Entry> {
+        let slot = self.slot(hash);
+        match self.entries[slot] {
+            Some(ref entry) if entry.hash == hash => {
+                self.hits += 1;
+                Some(entry.clone())
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn store(&mut self, entry: Entry) {
+        let slot = self.slot(entry.hash);
+        let keep_old = match self.entries[slot] {
+            Some(ref old) => old.hash == entry.hash && old.depth > entry.depth,
+            None => false
+        };
+        if !keep_old {
+            *self.entries.get_mut(slot) = Some(entry);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.mut_iter() {
+            *entry = None;
+        }
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
@@ -1,30 +1,130 @@
-use std::rand::random;
-use game::{Position, GameState};
+use std::rand::{Rng, SeedableRng, XorShiftRng};
+use game::PlayerIndex;
 use game::{North, East, South, West};
+use bitboard::Bitboard;
+use std::collections::RingBuf;
+use std::collections::Deque;
+use std::cmp::min;
+use time::precise_time_ns;
 
-pub fn random_bernoulli(p: f64) -> bool {
-    let x: f64 = random();
+/// Random number generator used by the engine and the bots. Everything
+/// random in a match derives from its seed, so a match can be reproduced.
+pub type GameRng = XorShiftRng;
+
+/// Derives an independent seed for stream `stream` (e.g. a player index)
+/// from `seed`, using the SplitMix64 finalizer.
+pub fn mix_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed + (stream + 1) * 0x9e3779b97f4a7c15;
+    z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9;
+    z = (z ^ (z >> 27)) * 0x94d049bb133111eb;
+    z ^ (z >> 31)
+}
+
+pub fn seeded_rng(seed: u64) -> GameRng {
+    let mixed = mix_seed(seed, 0);
+    // The constant words keep the XorShift state from ever being all zeroes.
+    SeedableRng::from_seed([mixed as u32, (mixed >> 32) as u32, 0x97830e05, 0x113ba7bb])
+}
+
+pub fn random_bernoulli<R: Rng>(rng: &mut R, p: f64) -> bool {
+    let x: f64 = rng.gen();
     x < p
 }
 
-pub fn flood_count(position: Position, game: &GameState) -> uint {
-    let mut flooded = Vec::from_elem(game.board_height, Vec::from_elem(game.board_width, false));
-    let mut count = 0u;
-    fn fill(pos: Position, game: &GameState, flooded: &mut Vec<Vec<bool>>, count: &mut uint) {
-        let (r, c) = pos;
-        if game.can_move_to(pos) && !(*flooded)[r][c] {
-            flooded.get_mut(r).grow_set(c, &false, true);
-            *count += 1;
-            fill(North.apply_to(pos), game, flooded, count);
-            fill(East.apply_to(pos), game, flooded, count);
-            fill(South.apply_to(pos), game, flooded, count);
-            fill(West.apply_to(pos), game, flooded, count);
-        }
+/// Longest time a bot spends searching a move, in nanoseconds.
+static MAX_SEARCH_TIME: u64 = 50000000;
+
+/// Nanoseconds a bot may spend searching: most of the time left until
+/// `deadline`, keeping a margin for sending the answer, but at most
+/// `MAX_SEARCH_TIME`.
+pub fn search_budget(deadline: u64) -> u64 {
+    let now = precise_time_ns();
+    if deadline <= now {
+        return 0;
+    }
+    min(MAX_SEARCH_TIME, (deadline - now) / 5 * 4)
+}
+
+/// Heuristic value of a game in progress for a player, higher being better.
+/// Bots only compare values of the same evaluation with each other and add
+/// their own scores for won and lost games.
+pub type Evaluation = fn(&Bitboard, PlayerIndex) -> f64;
+
+#[deriving(Clone, PartialEq, Eq, Show)]
+enum Owner {
+    Unreached,
+    Owned(PlayerIndex),
+    Contested
+}
+
+/// Result of dividing the free cells between the living players.
+#[deriving(Clone, Show)]
+pub struct Territory {
+    pub cells: Vec<uint>, // Cells each player reaches strictly before everyone else.
+    pub contested: uint // Cells that several players reach at the same time.
+}
+
+/// Spreads out from all living players' heads at once, one cell per step,
+/// and credits every free cell to the player that reaches it first. Cells
+/// reached by several players on the same step, and the cells behind them,
+/// are contested and credited to nobody.
+pub fn voronoi(board: &Bitboard) -> Territory {
+    let cell_count = board.width * board.height;
+    let mut owners = Vec::from_elem(cell_count, Unreached);
+    let mut distances = Vec::from_elem(cell_count, 0u);
+    let mut queue = RingBuf::new();
+    let mut territory = Territory {
+        cells: Vec::from_elem(board.heads.len(), 0u),
+        contested: 0
     };
-    fill(North.apply_to(position), game, &mut flooded, &mut count);
-    fill(East.apply_to(position), game, &mut flooded, &mut count);
-    fill(South.apply_to(position), game, &mut flooded, &mut count);
-    fill(West.apply_to(position), game, &mut flooded, &mut count);
-    count
+
+    for (p, &head) in board.heads.iter().enumerate() {
+        if board.alive[p] {
+            *owners.get_mut(head) = Owned(p);
+            queue.push_back(head);
+        }
+    }
+
+    loop {
+        let cell = match queue.pop_front() {
+            Some(cell) => cell,
+            None => break
+        };
+        let (owner, distance) = (owners[cell], distances[cell]);
+        for &direction in [North, East, South, West].iter() {
+            let next = match board.free_step(cell, direction) {
+                Some(next) => next,
+                None => continue
+            };
+            let next_owner = owners[next];
+            if next_owner == Unreached {
+                *owners.get_mut(next) = owner;
+                *distances.get_mut(next) = distance + 1;
+                queue.push_back(next);
+            } else if distances[next] == distance + 1 && next_owner != owner {
+                *owners.get_mut(next) = Contested;
+            }
+        }
+    }
+
+    for (cell, owner) in owners.iter().enumerate() {
+        if !board.is_free(cell) {
+            continue;
+        }
+        match *owner {
+            Owned(p) => *territory.cells.get_mut(p) += 1,
+            Contested => territory.contested += 1,
+            Unreached => {}
+        }
+    }
+    territory
 }
 
+/// Difference between `player`'s territory and the largest opponent's,
+/// as a fraction of the board.
+pub fn voronoi_evaluation(board: &Bitboard, player: PlayerIndex) -> f64 {
+    let territory = voronoi(board);
+    let theirs = board.opponents(player).iter().map(|&other| territory.cells[other]).max().unwrap_or(0);
+    let board_size = (board.width * board.height) as f64;
+    (territory.cells[player] as f64 - theirs as f64) / board_size
+}
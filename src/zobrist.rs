@@ -0,0 +1,49 @@
+//! Zobrist keys for hashing game states.
+//!
+//! A state's hash is the xor of the keys of its non-empty tiles, of every
+//! player's direction and of the player to move, so `GameState` can update
+//! it incrementally as tiles and players change. Rather than tables of
+//! random numbers sized for one board, keys are derived by mixing the
+//! feature's coordinates, which works for any board size.
+
+use game::{Position, Direction, Tile, Empty, Crash, Obstacle, PlayerWall, PlayerHead};
+use game::{GameStatus, PlayerTurn, AllPlayersTurn, PlayerIndex};
+use util::mix_seed;
+
+static TILE_STREAM: u64 = 0x74696c65;
+static DIRECTION_STREAM: u64 = 0x64697265;
+static STATUS_STREAM: u64 = 0x73746174;
+
+fn tile_kind(tile: &Tile) -> u64 {
+    match *tile {
+        Empty => 0,
+        Crash => 1,
+        Obstacle => 2,
+        PlayerWall(p) => 3 + 2 * p as u64,
+        PlayerHead(p) => 4 + 2 * p as u64
+    }
+}
+
+pub fn tile_key(position: Position, tile: &Tile) -> u64 {
+    match *tile {
+        Empty => 0,
+        _ => {
+            let (r, c) = position;
+            mix_seed(TILE_STREAM, ((r as u64) << 40) | ((c as u64) << 16) | tile_kind(tile))
+        }
+    }
+}
+
+pub fn direction_key(player: PlayerIndex, direction: Direction) -> u64 {
+    let d = direction.symbol() as u64;
+    mix_seed(DIRECTION_STREAM, ((player as u64) << 8) | d)
+}
+
+/// Key of the player to move; every finished game shares one key.
+pub fn status_key(status: &GameStatus) -> u64 {
+    match *status {
+        PlayerTurn(p) => mix_seed(STATUS_STREAM, p as u64),
+        AllPlayersTurn => mix_seed(STATUS_STREAM, 0xfffe),
+        _ => mix_seed(STATUS_STREAM, 0xffff)
+    }
+}